@@ -0,0 +1,206 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs::{self, File},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+/// A text file read from inside an archive.
+#[derive(Debug)]
+pub struct ArchiveFile {
+    /// Position of the entry in the archive
+    pub index: usize,
+    /// Path of the entry inside the archive
+    pub path: PathBuf,
+    pub contents: String,
+}
+
+/// Returns `true` if the file looks like a packed mod (`name_version.zip`).
+pub fn is_mod_zip<P: AsRef<Path>>(path: P) -> bool {
+    let path = path.as_ref();
+
+    path.is_file() && path.extension().is_some_and(|ext| ext == "zip")
+}
+
+/// Name of a packed mod, `name_1.2.3.zip` becomes `name`.
+pub fn mod_name<P: AsRef<Path>>(path: P) -> String {
+    let stem = path
+        .as_ref()
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    stem.rsplit_once('_')
+        .map_or_else(|| stem.clone(), |(name, _)| name.to_string())
+}
+
+/// Reads every file in the archive whose inner path passes `filter`.
+pub fn read_files<P: AsRef<Path>>(
+    path: P,
+    filter: impl Fn(&Path) -> bool,
+) -> Result<Vec<ArchiveFile>, Box<dyn Error>> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let mut files = Vec::new();
+
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+
+        let Some(path) = entry.enclosed_name() else {
+            continue;
+        };
+
+        if entry.is_dir() || !filter(&path) {
+            continue;
+        }
+
+        let mut contents = String::new();
+
+        entry.read_to_string(&mut contents)?;
+
+        files.push(ArchiveFile {
+            index,
+            path,
+            contents,
+        });
+    }
+
+    Ok(files)
+}
+
+/// Copies the archive to `destination`, replacing the contents of some entries
+/// and keeping every other entry as is.
+///
+/// The new archive is written next to the destination and then moved over it,
+/// so the destination may be the original archive.
+pub fn rewrite<P: AsRef<Path>, D: AsRef<Path>>(
+    path: P,
+    destination: D,
+    replacements: &HashMap<usize, String>,
+) -> Result<(), Box<dyn Error>> {
+    let path = path.as_ref();
+    let destination = destination.as_ref();
+    let temp = destination.with_extension("zip.tmp");
+
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let result = write_archive(path, &temp, replacements).and_then(|()| {
+        fs::rename(&temp, destination)?;
+
+        Ok(())
+    });
+
+    if result.is_err() {
+        let _ = fs::remove_file(temp);
+    }
+
+    result
+}
+
+fn write_archive(
+    path: &Path,
+    temp: &Path,
+    replacements: &HashMap<usize, String>,
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = ZipWriter::new(File::create(temp)?);
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for index in 0..archive.len() {
+        let entry = archive.by_index(index)?;
+
+        if let Some(contents) = replacements.get(&index) {
+            let name = entry.name().to_string();
+            let options = entry
+                .last_modified()
+                .map_or(options, |time| options.last_modified_time(time));
+
+            drop(entry);
+
+            writer.start_file(name, options)?;
+            writer.write_all(contents.as_bytes())?;
+        } else {
+            writer.raw_copy_file(entry)?;
+        }
+    }
+
+    writer.finish()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::test_dir;
+
+    fn write_zip(path: &Path, files: &[(&str, &str)]) {
+        let mut writer = ZipWriter::new(File::create(path).unwrap());
+
+        for (name, contents) in files {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn mod_name_drops_the_version() {
+        assert_eq!(mod_name("mods/some_mod_1.2.3.zip"), "some_mod");
+        assert_eq!(mod_name("plain.zip"), "plain");
+    }
+
+    #[test]
+    fn rewrite_replaces_entries_and_keeps_the_rest() {
+        let dir = test_dir("archive-rewrite");
+        let path = dir.join("mymod_1.0.0.zip");
+
+        write_zip(
+            &path,
+            &[("mymod/data.lua", "old"), ("mymod/info.json", "{}")],
+        );
+
+        let files = read_files(&path, |file| {
+            file.extension().is_some_and(|ext| ext == "lua")
+        })
+        .unwrap();
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].contents, "old");
+
+        rewrite(
+            &path,
+            &path,
+            &HashMap::from([(files[0].index, "new".to_string())]),
+        )
+        .unwrap();
+
+        let files = read_files(&path, |_| true).unwrap();
+
+        assert_eq!(files[0].contents, "new");
+        assert_eq!(files[1].contents, "{}");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failed_rewrite_leaves_no_temp_file() {
+        let dir = test_dir("archive-broken");
+        let path = dir.join("broken_1.0.0.zip");
+        let destination = dir.join("out").join("broken_1.0.0.zip");
+
+        fs::write(&path, "not a zip").unwrap();
+
+        assert!(rewrite(&path, &destination, &HashMap::new()).is_err());
+        assert!(!destination.with_extension("zip.tmp").exists());
+        assert!(!destination.exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,187 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// Files the rules are known to leave alone, so unchanged files aren't parsed
+/// again on the next run.
+///
+/// Every file maps to a key hashed from its contents, the fixer binary, the
+/// enabled rules with their options, the locales and the stylua settings. A
+/// file is skipped only if its current key matches the stored one.
+#[derive(Debug, Default)]
+pub struct Cache {
+    /// Where the cache is saved, `None` if caching is disabled
+    path: Option<PathBuf>,
+    /// Hash of everything but the rules and the file contents
+    context: String,
+    files: BTreeMap<PathBuf, String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheFile {
+    files: BTreeMap<PathBuf, String>,
+}
+
+impl Cache {
+    /// Loads the cache at `path`, starting over if it can't be read.
+    ///
+    /// Caching is disabled if the running binary can't be identified, results
+    /// of another build of the fixer can't be trusted.
+    pub fn load<P: Into<PathBuf>>(path: P, context: &str) -> Self {
+        let path = path.into();
+
+        let Some(binary) = binary_version() else {
+            return Self::default();
+        };
+
+        let files = fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| serde_json::from_str::<CacheFile>(&contents).ok())
+            .unwrap_or_default()
+            .files;
+
+        Self {
+            path: Some(path),
+            context: hash(&[binary.as_bytes(), context.as_bytes()]),
+            files,
+        }
+    }
+
+    /// Key of `contents` when fixed with rules identified by `rules`.
+    pub fn key(&self, rules: &str, contents: &str) -> String {
+        hash(&[
+            self.context.as_bytes(),
+            rules.as_bytes(),
+            contents.as_bytes(),
+        ])
+    }
+
+    /// Whether `path` was left alone the last time it had this key.
+    pub fn is_clean(&self, path: &Path, key: &str) -> bool {
+        self.path.is_some() && self.files.get(path).is_some_and(|cached| cached == key)
+    }
+
+    /// Remembers that the rules left `path` alone.
+    pub fn insert(&mut self, path: &Path, key: String) {
+        if self.path.is_some() {
+            self.files.insert(path.to_path_buf(), key);
+        }
+    }
+
+    /// Forgets `path`, e.g. because the rules changed it or reported something.
+    pub fn remove(&mut self, path: &Path) {
+        self.files.remove(path);
+    }
+
+    pub fn save(self) -> Result<(), Box<dyn Error>> {
+        let Some(path) = self.path else {
+            return Ok(());
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(
+            path,
+            serde_json::to_string(&CacheFile { files: self.files })?,
+        )?;
+
+        Ok(())
+    }
+}
+
+/// Size and modification time of the running binary, these change whenever
+/// the fixer is rebuilt, unlike its version.
+fn binary_version() -> Option<String> {
+    let metadata = fs::metadata(std::env::current_exe().ok()?).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+
+    Some(format!("{} {}", metadata.len(), modified.as_nanos()))
+}
+
+fn hash(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+
+    for part in parts {
+        // Lengths keep `ab` + `c` apart from `a` + `bc`
+        hasher.update(part.len().to_le_bytes());
+        hasher.update(part);
+    }
+
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::test_dir;
+
+    #[test]
+    fn remembers_clean_files_between_runs() {
+        let dir = test_dir("cache-roundtrip");
+        let path = dir.join("cache.json");
+        let file = Path::new("mod/data.lua");
+        let mut cache = Cache::load(&path, "locales");
+        let key = cache.key("recipe", "data:extend({})");
+
+        assert!(!cache.is_clean(file, &key));
+
+        cache.insert(file, key.clone());
+        cache.save().unwrap();
+
+        let cache = Cache::load(&path, "locales");
+
+        assert!(cache.is_clean(file, &key));
+        assert!(!cache.is_clean(file, &cache.key("recipe", "data:extend({ {} })")));
+        assert!(!cache.is_clean(file, &cache.key("recipe fluid-boxes", "data:extend({})")));
+
+        // Other locales or settings invalidate every key
+        let cache = Cache::load(&path, "other locales");
+
+        assert!(!cache.is_clean(file, &cache.key("recipe", "data:extend({})")));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn removed_files_are_fixed_again() {
+        let mut cache = Cache::load(test_dir("cache-remove").join("cache.json"), "");
+        let file = Path::new("mod/data.lua");
+        let key = cache.key("", "");
+
+        cache.insert(file, key.clone());
+        cache.remove(file);
+
+        assert!(!cache.is_clean(file, &key));
+    }
+
+    #[test]
+    fn disabled_cache_skips_nothing() {
+        let mut cache = Cache::default();
+        let file = Path::new("mod/data.lua");
+        let key = cache.key("", "");
+
+        cache.insert(file, key.clone());
+
+        assert!(!cache.is_clean(file, &key));
+        assert!(cache.save().is_ok());
+    }
+
+    #[test]
+    fn parts_are_hashed_with_their_lengths() {
+        assert_ne!(hash(&[b"ab", b"c"]), hash(&[b"a", b"bc"]));
+    }
+
+    #[test]
+    fn identifies_the_running_binary() {
+        assert!(binary_version().is_some());
+        assert_eq!(binary_version(), binary_version());
+    }
+}
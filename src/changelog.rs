@@ -0,0 +1,108 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const FILE_NAME: &str = "changelog.txt";
+
+/// Every version section starts with exactly this line.
+const SEPARATOR: &str =
+    "---------------------------------------------------------------------------------------------------";
+
+/// A version section in Factorio's changelog format, with every line listed
+/// under `category`.
+pub fn section(version: &str, date: &str, category: &str, lines: &[String]) -> String {
+    let mut output = format!("{SEPARATOR}\nVersion: {version}\nDate: {date}\n  {category}:\n");
+
+    for line in lines {
+        output.push_str("    - ");
+        output.push_str(line);
+        output.push('\n');
+    }
+
+    output
+}
+
+/// Adds `section` above the newest version of `existing`, returns `None` if
+/// the changelog already has a section for `version`.
+pub fn prepend(existing: &str, version: &str, section: &str) -> Option<String> {
+    if existing
+        .lines()
+        .filter_map(|line| line.strip_prefix("Version:"))
+        .any(|line| line.trim() == version)
+    {
+        return None;
+    }
+
+    Some(format!("{section}{existing}"))
+}
+
+/// Today as `YYYY-MM-DD` in UTC.
+pub fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() / 86_400);
+
+    // Civil date from days since 1970-01-01, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = days.cast_signed() + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_a_section() {
+        let lines = ["Fixed recipes.".to_string(), "Fixed graphics.".to_string()];
+
+        assert_eq!(
+            section("1.2.0", "2024-10-21", "Changes", &lines),
+            format!(
+                "{SEPARATOR}\nVersion: 1.2.0\nDate: 2024-10-21\n  Changes:\n    - Fixed recipes.\n    - Fixed graphics.\n"
+            )
+        );
+    }
+
+    #[test]
+    fn prepends_above_the_newest_version() {
+        let existing =
+            format!("{SEPARATOR}\nVersion: 1.1.0\nDate: 2024-01-01\n  Bugfixes:\n    - Old fix.\n");
+        let new = section("1.2.0", "2024-10-21", "Changes", &["New fix.".to_string()]);
+
+        assert_eq!(
+            prepend(&existing, "1.2.0", &new),
+            Some(format!("{new}{existing}"))
+        );
+        assert_eq!(prepend("", "1.2.0", &new), Some(new.clone()));
+    }
+
+    #[test]
+    fn keeps_an_existing_section_for_the_version() {
+        let existing = format!("{SEPARATOR}\nVersion:  1.2.0 \nDate: 2024-01-01\n");
+        let new = section("1.2.0", "2024-10-21", "Changes", &[]);
+
+        assert_eq!(prepend(&existing, "1.2.0", &new), None);
+    }
+
+    #[test]
+    fn dates_are_iso_formatted() {
+        let today = today();
+        let parts = today.split('-').collect::<Vec<_>>();
+
+        assert_eq!(today.len(), 10);
+        assert_eq!(
+            parts.iter().map(|part| part.len()).collect::<Vec<_>>(),
+            [4, 2, 2]
+        );
+        assert!(parts.iter().all(|part| part.parse::<u32>().is_ok()));
+    }
+}
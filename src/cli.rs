@@ -0,0 +1,268 @@
+use crate::{archive, rules::RuleSelection, DiffOutput};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Migrates Factorio mods to the 2.0 prototype format")]
+pub struct Cli {
+    /// Configuration file, defaults to `fixer.toml` in the current directory
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Apply enabled rules and rewrite changed Lua files in place
+    Fix {
+        #[command(flatten)]
+        args: RunArgs,
+
+        /// Print a unified diff for every changed file instead of writing it
+        #[arg(long, conflicts_with = "out_dir")]
+        dry_run: bool,
+
+        /// Write a copy of every mod with the fixes applied here, leaving the originals untouched
+        #[arg(long, value_name = "DIR")]
+        out_dir: Option<PathBuf>,
+
+        /// Add a section listing the applied fixes to the changelog.txt of every changed mod
+        #[arg(long)]
+        changelog: bool,
+    },
+    /// Run enabled rules without writing anything to disk, failing if any file would change
+    Check(RunArgs),
+    /// Re-run enabled rules in dry-run mode whenever a file of the mods changes
+    Watch {
+        #[command(flatten)]
+        args: RunArgs,
+
+        /// How often the mods are checked for changes, in milliseconds
+        #[arg(long, value_name = "MS", default_value_t = 500)]
+        interval: u64,
+    },
+    /// List every known rule and whether it is enabled
+    ListRules(ListRulesArgs),
+    /// Restore the files rewritten by the last run, or by the given run
+    Undo {
+        /// Run id as shown by `--list`
+        run: Option<String>,
+
+        /// List recorded runs instead of undoing one
+        #[arg(long, conflicts_with = "run")]
+        list: bool,
+
+        /// Restore files even if they were modified after the run
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct ListRulesArgs {
+    #[command(flatten)]
+    pub rules: RuleArgs,
+
+    /// Show the rules that apply to this mod, including its overrides
+    #[arg(long = "mod", value_name = "NAME")]
+    pub mod_name: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct RuleArgs {
+    /// Enable rules by id or group (`graphics.*`, `*`), comma separated
+    #[arg(long, value_name = "RULES", value_delimiter = ',')]
+    pub enable: Vec<String>,
+
+    /// Disable rules by id or group, takes precedence over `--enable`
+    #[arg(long, value_name = "RULES", value_delimiter = ',')]
+    pub disable: Vec<String>,
+}
+
+impl RuleArgs {
+    pub fn selection(&self) -> RuleSelection {
+        RuleSelection::new(self.enable.clone(), self.disable.clone())
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct RunArgs {
+    /// Factorio installation directory, `data` and `mods` are looked up inside it
+    #[arg(long, value_name = "DIR")]
+    pub factorio: Option<PathBuf>,
+
+    /// Factorio `data` directory, used to load `base`, `core` and `quality` locales
+    #[arg(long, value_name = "DIR")]
+    pub data: Option<PathBuf>,
+
+    /// Factorio `mods` directory, every mod inside it is used as a locale source
+    #[arg(long, value_name = "DIR")]
+    pub mods_dir: Option<PathBuf>,
+
+    /// Additional locale directories (containing `en/*.cfg`)
+    #[arg(long = "locale", value_name = "DIR")]
+    pub locales: Vec<PathBuf>,
+
+    #[command(flatten)]
+    pub rules: RuleArgs,
+
+    #[command(flatten)]
+    pub report: ReportArgs,
+
+    /// Rewrite packed mods (`.zip`) in place, otherwise they are only read
+    #[arg(long)]
+    pub write_zips: bool,
+
+    /// How changed Lua files are formatted
+    #[arg(long, value_name = "STYLE")]
+    pub formatting: Option<Formatting>,
+
+    /// How tables are recognized as prototypes
+    #[arg(long, value_name = "MODE")]
+    pub detection: Option<Detection>,
+
+    /// Fix every file from scratch instead of skipping files known to be unchanged
+    #[arg(long)]
+    pub no_cache: bool,
+
+    /// Directory names that are never visited, replaces the configured list
+    #[arg(long, value_name = "NAME", value_delimiter = ',')]
+    pub exclude: Vec<String>,
+
+    /// Mod directories to process, or directories containing mods
+    #[arg(value_name = "MOD")]
+    pub mods: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Formatting {
+    /// Format the whole file with stylua
+    #[default]
+    File,
+    /// Only format the tables rules changed, leaving the rest of the file as it was
+    Tables,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Detection {
+    /// Tables passed to `data:extend` or assigned to `data.raw[type][name]`,
+    /// and tables with a `type` and a `name` outside of other prototypes
+    #[default]
+    Heuristic,
+    /// Only tables passed to `data:extend` or assigned to `data.raw[type][name]`
+    Strict,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    /// Colored diagnostics grouped by mod
+    #[default]
+    Text,
+    /// Every rule application with before/after snippets and summary counts
+    Json,
+}
+
+#[derive(Debug, Args)]
+pub struct ReportArgs {
+    /// How diagnostics are reported at the end of the run
+    #[arg(long = "report", value_name = "FORMAT", default_value = "text")]
+    pub format: ReportFormat,
+
+    /// Write the report to a file instead of stdout
+    #[arg(long, value_name = "FILE")]
+    pub report_file: Option<PathBuf>,
+}
+
+impl ReportArgs {
+    /// Diffs go to stderr when a JSON report is printed to stdout, so the
+    /// report stays parseable.
+    pub fn diff_output(&self) -> DiffOutput {
+        if self.format == ReportFormat::Json && self.report_file.is_none() {
+            DiffOutput::Stderr
+        } else {
+            DiffOutput::Stdout
+        }
+    }
+}
+
+/// Returns `true` if the directory looks like a mod (has `info.json`).
+pub fn is_mod_dir<P: AsRef<Path>>(path: P) -> bool {
+    let path = path.as_ref();
+
+    path.is_dir() && path.join("info.json").is_file()
+}
+
+/// Returns `true` if the path is a mod directory or a packed mod.
+pub fn is_mod<P: AsRef<Path>>(path: P) -> bool {
+    is_mod_dir(&path) || archive::is_mod_zip(&path)
+}
+
+/// Expands every given path into mods: a path is either a mod itself (a directory
+/// or a zip) or a workspace whose direct children are mods.
+pub fn find_mods(paths: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    let mut mods = Vec::new();
+
+    for path in paths {
+        if is_mod(path) {
+            mods.push(path.clone());
+        } else {
+            let mut children = path
+                .read_dir()?
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| is_mod(path))
+                .collect::<Vec<_>>();
+
+            children.sort();
+
+            mods.extend(children);
+        }
+    }
+
+    Ok(mods)
+}
+
+pub fn mod_name<P: AsRef<Path>>(path: P) -> String {
+    if archive::is_mod_zip(&path) {
+        return archive::mod_name(path);
+    }
+
+    path.as_ref()
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_report_on_stdout_moves_diffs_to_stderr() {
+        let report = |format, report_file: Option<&str>| ReportArgs {
+            format,
+            report_file: report_file.map(PathBuf::from),
+        };
+
+        assert_eq!(
+            report(ReportFormat::Json, None).diff_output(),
+            DiffOutput::Stderr
+        );
+        assert_eq!(
+            report(ReportFormat::Json, Some("report.json")).diff_output(),
+            DiffOutput::Stdout
+        );
+        assert_eq!(
+            report(ReportFormat::Text, None).diff_output(),
+            DiffOutput::Stdout
+        );
+    }
+}
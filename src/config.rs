@@ -0,0 +1,408 @@
+use crate::{
+    cli::{Detection, Formatting, RunArgs},
+    rules::{self, RuleOptions, RuleSelection},
+};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Project configuration, usually checked in as `fixer.toml` next to the mods.
+///
+/// Relative paths are resolved against the directory containing the file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Factorio installation, `data` and `mods` are looked up inside it
+    pub factorio: Option<PathBuf>,
+    pub data: Option<PathBuf>,
+    pub mods_dir: Option<PathBuf>,
+    pub locales: Vec<PathBuf>,
+    /// Mod directories to process, or directories containing mods
+    pub mods: Vec<PathBuf>,
+    /// Directory names that are never visited
+    pub exclude: Option<Vec<String>>,
+    /// Whether packed mods are rewritten in place when fixing
+    pub write_zips: bool,
+    /// Fixed mods are written here instead of in place
+    pub out_dir: Option<PathBuf>,
+    /// Results of unchanged files, `cache.json` in the journal directory by default
+    pub cache: Option<PathBuf>,
+    pub formatting: Formatting,
+    /// How tables are recognized as prototypes
+    pub detection: Detection,
+    /// Whether fixing adds a section to the changelog.txt of every changed mod
+    pub changelog: bool,
+    /// Backups of files rewritten in place, used by `undo`
+    pub journal: Option<PathBuf>,
+    pub rules: RulesConfig,
+    /// Rule selection and options for single mods, keyed by mod directory name
+    pub overrides: HashMap<String, RulesConfig>,
+    /// Same keys as `stylua.toml`
+    pub stylua: Option<toml::Table>,
+    /// Rule selection given on the command line, applied after everything else
+    #[serde(skip)]
+    pub cli_rules: RuleSelection,
+    /// Set by `--no-cache`, every file is fixed from scratch
+    #[serde(skip)]
+    pub no_cache: bool,
+    /// Directory containing the configuration file, the current directory if
+    /// there is none
+    #[serde(skip)]
+    pub root: PathBuf,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RulesConfig {
+    pub enable: Vec<String>,
+    pub disable: Vec<String>,
+    /// Options keyed by rule id
+    pub options: HashMap<String, toml::Table>,
+}
+
+impl RulesConfig {
+    pub fn selection(&self) -> RuleSelection {
+        RuleSelection::new(self.enable.clone(), self.disable.clone())
+    }
+}
+
+impl Config {
+    pub const FILE_NAME: &'static str = "fixer.toml";
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+
+        let mut config: Self = toml::from_str(&fs::read_to_string(path)?)
+            .map_err(|error| format!("{}: {error}", path.display()))?;
+
+        if let Some(root) = path.parent() {
+            config.resolve_paths(root);
+            config.root = root.to_path_buf();
+        }
+
+        Ok(config)
+    }
+
+    /// Loads the given file, or the closest `fixer.toml` in the current
+    /// directory or above it.
+    pub fn discover(path: Option<&Path>) -> Result<Self, Box<dyn Error>> {
+        if let Some(path) = path {
+            return Self::load(path);
+        }
+
+        let current = std::env::current_dir()?;
+
+        current
+            .ancestors()
+            .map(|dir| dir.join(Self::FILE_NAME))
+            .find(|path| path.is_file())
+            .map_or_else(
+                || {
+                    Ok(Self {
+                        root: current.clone(),
+                        ..Self::default()
+                    })
+                },
+                Self::load,
+            )
+    }
+
+    fn resolve_paths(&mut self, root: &Path) {
+        let resolve = |path: &mut PathBuf| *path = root.join(&*path);
+
+        self.factorio.iter_mut().for_each(resolve);
+        self.data.iter_mut().for_each(resolve);
+        self.mods_dir.iter_mut().for_each(resolve);
+        self.out_dir.iter_mut().for_each(resolve);
+        self.journal.iter_mut().for_each(resolve);
+        self.cache.iter_mut().for_each(resolve);
+        self.locales.iter_mut().for_each(resolve);
+        self.mods.iter_mut().for_each(resolve);
+    }
+
+    /// Command line flags take precedence over the file.
+    pub fn apply_args(&mut self, args: &RunArgs) {
+        if args.factorio.is_some() {
+            self.factorio.clone_from(&args.factorio);
+        }
+
+        if args.data.is_some() {
+            self.data.clone_from(&args.data);
+        }
+
+        if args.mods_dir.is_some() {
+            self.mods_dir.clone_from(&args.mods_dir);
+        }
+
+        if !args.exclude.is_empty() {
+            self.exclude = Some(args.exclude.clone());
+        }
+
+        if args.write_zips {
+            self.write_zips = true;
+        }
+
+        if let Some(formatting) = args.formatting {
+            self.formatting = formatting;
+        }
+
+        if let Some(detection) = args.detection {
+            self.detection = detection;
+        }
+
+        if args.no_cache {
+            self.no_cache = true;
+        }
+
+        if !args.mods.is_empty() {
+            self.mods.clone_from(&args.mods);
+        }
+
+        self.locales.extend(args.locales.iter().cloned());
+
+        self.cli_rules = args.rules.selection();
+    }
+
+    pub fn data_dir(&self) -> Option<PathBuf> {
+        self.data
+            .clone()
+            .or_else(|| self.factorio.as_ref().map(|path| path.join("data")))
+    }
+
+    pub fn mods_dir(&self) -> Option<PathBuf> {
+        self.mods_dir
+            .clone()
+            .or_else(|| self.factorio.as_ref().map(|path| path.join("mods")))
+    }
+
+    /// Backups of rewritten files, `.fixer` next to the configuration file by default.
+    pub fn journal_dir(&self) -> PathBuf {
+        self.journal
+            .clone()
+            .unwrap_or_else(|| self.root.join(".fixer"))
+    }
+
+    /// Cache file to use, `None` if caching is disabled.
+    pub fn cache_file(&self) -> Option<PathBuf> {
+        if self.no_cache {
+            return None;
+        }
+
+        Some(
+            self.cache
+                .clone()
+                .unwrap_or_else(|| self.journal_dir().join("cache.json")),
+        )
+    }
+
+    pub fn exclude(&self) -> Vec<String> {
+        self.exclude
+            .clone()
+            .unwrap_or_else(|| vec!["graphics".to_string(), "locale".to_string()])
+    }
+
+    /// Effective rule selection for the mod, or for every mod without an override.
+    pub fn selection(&self, mod_name: Option<&str>) -> RuleSelection {
+        let mut selection = self.rules.selection();
+
+        if let Some(rules) = mod_name.and_then(|name| self.overrides.get(name)) {
+            selection.merge(rules.selection());
+        }
+
+        selection.merge(self.cli_rules.clone());
+
+        selection
+    }
+
+    /// Effective rule options for the mod, override keys replace global ones.
+    pub fn options(&self, mod_name: Option<&str>) -> HashMap<String, RuleOptions> {
+        let mut options = self
+            .rules
+            .options
+            .iter()
+            .map(|(id, options)| (id.clone(), RuleOptions::new(options.clone())))
+            .collect::<HashMap<_, _>>();
+
+        if let Some(rules) = mod_name.and_then(|name| self.overrides.get(name)) {
+            for (id, table) in &rules.options {
+                options.entry(id.clone()).or_default().merge(table);
+            }
+        }
+
+        options
+    }
+
+    /// Makes sure every rule pattern and option refers to a known rule, and
+    /// every option value can be used by it.
+    pub fn validate_rules(&self) -> Result<(), String> {
+        for (scope, rules) in std::iter::once(("rules".to_string(), &self.rules)).chain(
+            self.overrides
+                .iter()
+                .map(|(name, rules)| (format!("overrides.{name}"), rules)),
+        ) {
+            if let Some(pattern) = rules.selection().unknown_patterns().first() {
+                return Err(format!(
+                    "no rule matches `{pattern}` in [{scope}], see `list-rules`"
+                ));
+            }
+
+            for (id, table) in &rules.options {
+                let Some(options) = rules::options(id) else {
+                    return Err(format!(
+                        "options given for unknown rule `{id}` in [{scope}]"
+                    ));
+                };
+
+                for (name, value) in table {
+                    let option = options
+                        .iter()
+                        .find(|option| option.name == name)
+                        .ok_or_else(|| {
+                            format!("rule `{id}` has no option `{name}` in [{scope}]")
+                        })?;
+
+                    (option.check)(value).map_err(|error| {
+                        format!("invalid option `{name}` of rule `{id}` in [{scope}]: {error}")
+                    })?;
+                }
+            }
+        }
+
+        if let Some(pattern) = self.cli_rules.unknown_patterns().first() {
+            return Err(format!("no rule matches `{pattern}`, see `list-rules`"));
+        }
+
+        Ok(())
+    }
+
+    /// The `[stylua]` section, checked against the settings stylua understands.
+    pub fn stylua(&self) -> Result<toml::Table, Box<dyn Error>> {
+        let table = self.stylua.clone().unwrap_or_default();
+
+        toml::Value::Table(table.clone())
+            .try_into::<stylua_lib::Config>()
+            .map_err(|error| format!("invalid [stylua] section: {error}"))?;
+
+        Ok(table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::test_dir;
+
+    fn config(source: &str) -> Config {
+        toml::from_str(source).unwrap()
+    }
+
+    #[test]
+    fn journal_defaults_next_to_the_configuration_file() {
+        let dir = test_dir("config-journal");
+
+        fs::write(dir.join(Config::FILE_NAME), "mods = [\"mods\"]\n").unwrap();
+
+        let config = Config::load(dir.join(Config::FILE_NAME)).unwrap();
+
+        assert_eq!(config.journal_dir(), dir.join(".fixer"));
+        assert_eq!(config.mods, vec![dir.join("mods")]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn override_can_enable_a_rule_disabled_by_group() {
+        let config = config(
+            r#"
+            [rules]
+            disable = ["graphics.*"]
+
+            [overrides.mymod]
+            enable = ["graphics.beam"]
+            "#,
+        );
+
+        assert!(config
+            .selection(Some("mymod"))
+            .is_enabled("graphics.beam", false));
+        assert!(!config.selection(None).is_enabled("graphics.beam", true));
+        assert!(!config
+            .selection(Some("mymod"))
+            .is_enabled("graphics.turret", true));
+    }
+
+    #[test]
+    fn command_line_overrides_the_file() {
+        let mut config = config(
+            r#"
+            [rules]
+            disable = ["graphics.*"]
+            "#,
+        );
+
+        config.cli_rules = RuleSelection::new(vec!["graphics.hr-version".to_string()], Vec::new());
+
+        assert!(config
+            .selection(None)
+            .is_enabled("graphics.hr-version", false));
+    }
+
+    #[test]
+    fn rejects_options_of_the_wrong_type() {
+        let config = config(
+            r#"
+            [rules.options."fluid-boxes"]
+            grid = "0.5"
+            "#,
+        );
+
+        assert!(config.validate_rules().unwrap_err().contains("`grid`"));
+    }
+
+    #[test]
+    fn rejects_a_zero_grid() {
+        let config = config(
+            r#"
+            [overrides.mymod.options."fluid-boxes"]
+            grid = 0
+            "#,
+        );
+
+        assert!(config
+            .validate_rules()
+            .unwrap_err()
+            .contains("[overrides.mymod]"));
+    }
+
+    #[test]
+    fn rejects_unknown_options() {
+        let config = config(
+            r#"
+            [rules.options."fluid-boxes"]
+            gird = 0.5
+            "#,
+        );
+
+        assert!(config.validate_rules().is_err());
+    }
+
+    #[test]
+    fn accepts_valid_options() {
+        let config = config(
+            r#"
+            [rules.options."fluid-boxes"]
+            grid = 1
+
+            [rules.options."info.dependencies"]
+            removed = ["old-lib"]
+            renamed = { old = "new" }
+            "#,
+        );
+
+        assert!(config.validate_rules().is_ok());
+    }
+}
@@ -0,0 +1,451 @@
+use full_moon::tokenizer::Position;
+use owo_colors::OwoColorize;
+use serde::Serialize;
+use std::{
+    fmt::{self, Write},
+    path::PathBuf,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The rule rewrote the table
+    Fixed,
+    /// The rule matched but deliberately left the table alone
+    Skipped,
+    /// The rule matched but couldn't decide how to fix the table
+    Warning,
+    /// The file couldn't be processed at all
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Self::Fixed => "fixed",
+            Self::Skipped => "skipped",
+            Self::Warning => "warning",
+            Self::Error => "error",
+        })
+    }
+}
+
+/// 1-based line and column in the source file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl From<Position> for Location {
+    fn from(position: Position) -> Self {
+        Self {
+            line: position.line(),
+            column: position.character(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// `None` for problems that aren't caused by a rule, like parse errors
+    pub rule: Option<&'static str>,
+    #[serde(skip)]
+    pub mod_name: String,
+    #[serde(skip)]
+    pub file: PathBuf,
+    pub location: Option<Location>,
+    /// Prototype `type`, if the table has one
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    /// Prototype `name`, if the table has one
+    pub name: Option<String>,
+    pub message: String,
+    /// The table before the rule ran, only recorded for reports
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<String>,
+    /// The table after the rule ran, only recorded for reports
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+}
+
+impl Diagnostic {
+    /// `path:line:column`, the format editors and terminals can jump to.
+    pub fn source(&self) -> String {
+        self.location.map_or_else(
+            || self.file.display().to_string(),
+            |location| {
+                format!(
+                    "{}:{}:{}",
+                    self.file.display(),
+                    location.line,
+                    location.column
+                )
+            },
+        )
+    }
+
+    /// `type/name` of the prototype the diagnostic is about.
+    pub fn prototype(&self) -> Option<String> {
+        match (&self.kind, &self.name) {
+            (Some(kind), Some(name)) => Some(format!("{kind}/{name}")),
+            (None, Some(name)) => Some(name.clone()),
+            (Some(kind), None) => Some(kind.clone()),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Collects diagnostics emitted while visiting mods.
+///
+/// The visitor keeps the current mod, file, rule and prototype up to date, so
+/// rules only have to provide a severity and a message.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    entries: Vec<Diagnostic>,
+    /// Files that were (or in a dry run would be) rewritten, with their mod
+    changed: Vec<(String, PathBuf)>,
+    mod_name: String,
+    file: PathBuf,
+    location: Option<Location>,
+    rule: Option<&'static str>,
+    kind: Option<String>,
+    name: Option<String>,
+}
+
+impl Diagnostics {
+    pub fn set_mod<T: Into<String>>(&mut self, name: T) {
+        self.mod_name = name.into();
+    }
+
+    pub fn set_file<P: Into<PathBuf>>(&mut self, path: P) {
+        self.file = path.into();
+        self.location = None;
+        self.rule = None;
+        self.kind = None;
+        self.name = None;
+    }
+
+    pub const fn set_rule(&mut self, rule: Option<&'static str>) {
+        self.rule = rule;
+    }
+
+    /// Sets the table being visited, its position is used when a rule doesn't give one.
+    pub fn set_prototype(
+        &mut self,
+        kind: Option<String>,
+        name: Option<String>,
+        position: Option<Position>,
+    ) {
+        self.kind = kind;
+        self.name = name;
+        self.location = position.map(Location::from);
+    }
+
+    pub fn push<T: Into<String>>(&mut self, severity: Severity, message: T) {
+        self.push_at(severity, None, message);
+    }
+
+    /// Pushes a diagnostic pointing at `position`, e.g. a specific field of the table.
+    pub fn push_at<T: Into<String>>(
+        &mut self,
+        severity: Severity,
+        position: Option<Position>,
+        message: T,
+    ) {
+        self.entries.push(Diagnostic {
+            severity,
+            rule: self.rule,
+            mod_name: self.mod_name.clone(),
+            file: self.file.clone(),
+            location: position.map(Location::from).or(self.location),
+            kind: self.kind.clone(),
+            name: self.name.clone(),
+            message: message.into(),
+            before: None,
+            after: None,
+        });
+    }
+
+    /// Records the table text around a rule for every diagnostic pushed since `since`.
+    pub fn set_snippets(&mut self, since: usize, before: &str, after: &str) {
+        for diagnostic in self.entries.iter_mut().skip(since) {
+            diagnostic.before = Some(before.trim().to_string());
+            diagnostic.after = Some(after.trim().to_string());
+        }
+    }
+
+    /// Moves every diagnostic and changed file of `other` to the end of this one.
+    pub fn append(&mut self, mut other: Self) {
+        self.entries.append(&mut other.entries);
+        self.changed.append(&mut other.changed);
+    }
+
+    /// Marks the current file as rewritten.
+    pub fn mark_changed(&mut self) {
+        self.changed
+            .push((self.mod_name.clone(), self.file.clone()));
+    }
+
+    pub fn changed(&self) -> &[(String, PathBuf)] {
+        &self.changed
+    }
+
+    pub const fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn fixed<T: Into<String>>(&mut self, message: T) {
+        self.push(Severity::Fixed, message);
+    }
+
+    pub fn skipped<T: Into<String>>(&mut self, message: T) {
+        self.push(Severity::Skipped, message);
+    }
+
+    pub fn warning<T: Into<String>>(&mut self, message: T) {
+        self.push(Severity::Warning, message);
+    }
+
+    pub fn error<T: Into<String>>(&mut self, message: T) {
+        self.push(Severity::Error, message);
+    }
+
+    pub fn entries(&self) -> &[Diagnostic] {
+        &self.entries
+    }
+
+    pub fn count(&self, severity: Severity) -> usize {
+        self.entries
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == severity)
+            .count()
+    }
+
+    /// Lists every changed file together with the prototypes that were fixed in it.
+    pub fn render_changes(&self) -> String {
+        let mut changed = self.changed.iter().collect::<Vec<_>>();
+
+        changed.sort();
+
+        let mut output = String::new();
+
+        for (mod_name, file) in changed {
+            writeln!(output, "[{}] {}", mod_name.bright_blue(), file.display()).unwrap();
+
+            let mut fixes = self
+                .entries
+                .iter()
+                .filter(|diagnostic| {
+                    diagnostic.severity == Severity::Fixed && &diagnostic.file == file
+                })
+                .collect::<Vec<_>>();
+
+            fixes.sort_by_key(|diagnostic| diagnostic.location);
+
+            for diagnostic in fixes {
+                write!(
+                    output,
+                    "    {} {}",
+                    "-->".bright_blue(),
+                    diagnostic.source()
+                )
+                .unwrap();
+
+                if let Some(prototype) = diagnostic.prototype() {
+                    write!(output, " {}", prototype.bright_cyan()).unwrap();
+                }
+
+                if let Some(rule) = diagnostic.rule {
+                    write!(output, " ({})", rule.bright_magenta()).unwrap();
+                }
+
+                output.push('\n');
+            }
+        }
+
+        output
+    }
+
+    /// Lists every error, so failures aren't lost among the rest of the output.
+    pub fn render_failures(&self) -> String {
+        let mut errors = self
+            .entries
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .collect::<Vec<_>>();
+
+        errors.sort_by(|a, b| {
+            (&a.mod_name, &a.file, a.location).cmp(&(&b.mod_name, &b.file, b.location))
+        });
+
+        let mut output = String::new();
+
+        for diagnostic in errors {
+            writeln!(
+                output,
+                "[{}] {}: {}",
+                diagnostic.mod_name.bright_blue(),
+                diagnostic.source(),
+                diagnostic.message
+            )
+            .unwrap();
+        }
+
+        output
+    }
+
+    /// Renders every diagnostic grouped by mod, followed by a summary.
+    pub fn render(&self) -> String {
+        let mut entries = self.entries.iter().collect::<Vec<_>>();
+
+        entries.sort_by(|a, b| {
+            (&a.mod_name, &a.file, a.location).cmp(&(&b.mod_name, &b.file, b.location))
+        });
+
+        let mut output = String::new();
+        let mut current = None;
+
+        for diagnostic in entries {
+            if current != Some(&diagnostic.mod_name) {
+                current = Some(&diagnostic.mod_name);
+
+                writeln!(output, "[{}]", diagnostic.mod_name.bright_blue()).unwrap();
+            }
+
+            let severity = format!("{:<8}", diagnostic.severity);
+            let severity = match diagnostic.severity {
+                Severity::Fixed => severity.bright_green().to_string(),
+                Severity::Skipped => severity.bright_black().to_string(),
+                Severity::Warning => severity.bright_yellow().to_string(),
+                Severity::Error => severity.bright_red().to_string(),
+            };
+
+            write!(output, "  {severity}").unwrap();
+
+            if let Some(rule) = diagnostic.rule {
+                write!(output, " {}", rule.bright_magenta()).unwrap();
+            }
+
+            if let Some(prototype) = diagnostic.prototype() {
+                write!(output, " {}", prototype.bright_cyan()).unwrap();
+            }
+
+            writeln!(output, ": {}", diagnostic.message).unwrap();
+            writeln!(
+                output,
+                "    {} {}",
+                "-->".bright_blue(),
+                diagnostic.source()
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            output,
+            "{} fixed, {} skipped, {} warnings, {} errors",
+            self.count(Severity::Fixed).bright_green(),
+            self.count(Severity::Skipped).bright_black(),
+            self.count(Severity::Warning).bright_yellow(),
+            self.count(Severity::Error).bright_red(),
+        )
+        .unwrap();
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostics_carry_the_current_context() {
+        let mut diagnostics = Diagnostics::default();
+
+        diagnostics.set_mod("mymod");
+        diagnostics.set_file("prototypes/item.lua");
+        diagnostics.set_rule(Some("recipe.localised-name"));
+        diagnostics.set_prototype(Some("recipe".to_string()), Some("plate".to_string()), None);
+        diagnostics.fixed("added localised_name");
+
+        let diagnostic = &diagnostics.entries()[0];
+
+        assert_eq!(diagnostic.mod_name, "mymod");
+        assert_eq!(diagnostic.rule, Some("recipe.localised-name"));
+        assert_eq!(diagnostic.prototype().as_deref(), Some("recipe/plate"));
+        assert_eq!(diagnostic.source(), "prototypes/item.lua");
+
+        // A new file starts without a rule or prototype
+        diagnostics.set_file("data.lua");
+        diagnostics.error("could not be processed");
+
+        let diagnostic = &diagnostics.entries()[1];
+
+        assert_eq!(diagnostic.rule, None);
+        assert_eq!(diagnostic.prototype(), None);
+    }
+
+    #[test]
+    fn sources_include_the_location() {
+        let diagnostic = Diagnostic {
+            severity: Severity::Warning,
+            rule: None,
+            mod_name: String::new(),
+            file: PathBuf::from("data.lua"),
+            location: Some(Location { line: 3, column: 7 }),
+            kind: None,
+            name: Some("foo".to_string()),
+            message: String::new(),
+            before: None,
+            after: None,
+        };
+
+        assert_eq!(diagnostic.source(), "data.lua:3:7");
+        assert_eq!(diagnostic.prototype().as_deref(), Some("foo"));
+    }
+
+    #[test]
+    fn appending_keeps_changed_files_and_counts() {
+        let mut diagnostics = Diagnostics::default();
+        let mut other = Diagnostics::default();
+
+        diagnostics.set_mod("a");
+        diagnostics.warning("first");
+        other.set_mod("b");
+        other.set_file("b/data.lua");
+        other.mark_changed();
+        other.error("second");
+        other.skipped("third");
+        diagnostics.append(other);
+
+        assert_eq!(diagnostics.len(), 3);
+        assert_eq!(diagnostics.count(Severity::Error), 1);
+        assert_eq!(diagnostics.count(Severity::Fixed), 0);
+        assert_eq!(
+            diagnostics.changed(),
+            [("b".to_string(), PathBuf::from("b/data.lua"))]
+        );
+    }
+
+    #[test]
+    fn snippets_are_only_recorded_since_the_given_entry() {
+        let mut diagnostics = Diagnostics::default();
+
+        diagnostics.fixed("before");
+        diagnostics.fixed("after");
+        diagnostics.set_snippets(1, " { a = 1 } ", "{ b = 1 }\n");
+
+        assert_eq!(diagnostics.entries()[0].before, None);
+        assert_eq!(
+            diagnostics.entries()[1].before.as_deref(),
+            Some("{ a = 1 }")
+        );
+        assert_eq!(diagnostics.entries()[1].after.as_deref(), Some("{ b = 1 }"));
+    }
+}
@@ -0,0 +1,35 @@
+use owo_colors::OwoColorize;
+use similar::{ChangeTag, TextDiff};
+use std::{fmt::Write, path::Path};
+
+/// Renders a colored unified diff between the original and the fixed text.
+pub fn unified_diff<P: AsRef<Path>>(path: P, old: &str, new: &str) -> String {
+    let path = path.as_ref().display().to_string();
+    let diff = TextDiff::from_lines(old, new);
+
+    let mut output = String::new();
+
+    writeln!(output, "{}", format!("--- {path}").bright_red()).unwrap();
+    writeln!(output, "{}", format!("+++ {path}").bright_green()).unwrap();
+
+    for hunk in diff.unified_diff().context_radius(3).iter_hunks() {
+        writeln!(output, "{}", hunk.header().bright_cyan()).unwrap();
+
+        for change in hunk.iter_changes() {
+            let line = format!("{}{}", change.tag(), change.value().trim_end_matches('\n'));
+
+            match change.tag() {
+                ChangeTag::Delete => writeln!(output, "{}", line.red()),
+                ChangeTag::Insert => writeln!(output, "{}", line.green()),
+                ChangeTag::Equal => writeln!(output, "{line}"),
+            }
+            .unwrap();
+
+            if change.missing_newline() {
+                output.push_str("\\ No newline at end of file\n");
+            }
+        }
+    }
+
+    output
+}
@@ -0,0 +1,266 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Everything a single run rewrote in place.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Run {
+    pub id: String,
+    /// Seconds since the Unix epoch
+    pub created: u64,
+    pub files: Vec<Entry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub path: PathBuf,
+    /// Copy of the original file, relative to the run directory, `None` if the
+    /// run created the file
+    pub backup: Option<PathBuf>,
+    pub original_hash: String,
+    pub written_hash: String,
+}
+
+/// Outcome of restoring a single file.
+#[derive(Debug)]
+pub enum Restored {
+    Restored(PathBuf),
+    /// The file changed after the run, restoring it would lose those changes
+    Modified(PathBuf),
+}
+
+/// Records backups of every file rewritten during a run, so it can be undone.
+///
+/// Nothing is created on disk until the first file is recorded.
+#[derive(Debug)]
+pub struct Journal {
+    dir: PathBuf,
+    run: Run,
+}
+
+impl Journal {
+    pub const FILE_NAME: &'static str = "journal.json";
+
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+
+        let root = root.as_ref();
+        let mut id = created.to_string();
+        let mut attempt = 1;
+
+        while root.join(&id).exists() {
+            id = format!("{created}-{attempt}");
+            attempt += 1;
+        }
+
+        Self {
+            dir: root.join(&id),
+            run: Run {
+                id,
+                created,
+                files: Vec::new(),
+            },
+        }
+    }
+
+    /// Backs up `original` after `path` was overwritten with `written`, a
+    /// missing `original` means the file was created.
+    ///
+    /// Only called once the write succeeded, so every entry matches a file
+    /// that existed on disk.
+    pub fn record(
+        &mut self,
+        path: &Path,
+        original: Option<&[u8]>,
+        written: &[u8],
+    ) -> Result<(), Box<dyn Error>> {
+        let file_name = path.file_name().unwrap_or_default();
+        let backup = original
+            .map(|original| {
+                let backup = PathBuf::from("files").join(format!(
+                    "{}-{}",
+                    self.run.files.len(),
+                    file_name.to_string_lossy()
+                ));
+
+                fs::create_dir_all(self.dir.join("files"))?;
+                fs::write(self.dir.join(&backup), original)?;
+
+                Ok::<_, std::io::Error>(backup)
+            })
+            .transpose()?;
+
+        // The parent is canonicalized, the file itself may not exist yet
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.canonicalize()?,
+            _ => std::env::current_dir()?,
+        };
+
+        self.run.files.push(Entry {
+            path: parent.join(file_name),
+            backup,
+            original_hash: original.map(hash).unwrap_or_default(),
+            written_hash: hash(written),
+        });
+
+        fs::create_dir_all(&self.dir)?;
+
+        // Saved after every file so an interrupted run can still be undone
+        fs::write(
+            self.dir.join(Self::FILE_NAME),
+            serde_json::to_string_pretty(&self.run)?,
+        )?;
+
+        Ok(())
+    }
+
+    pub const fn run(&self) -> &Run {
+        &self.run
+    }
+
+    /// Every recorded run, oldest first.
+    pub fn list<P: AsRef<Path>>(root: P) -> Result<Vec<Run>, Box<dyn Error>> {
+        let root = root.as_ref();
+
+        if !root.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut runs = Vec::new();
+
+        for entry in root.read_dir()?.flatten() {
+            let path = entry.path().join(Self::FILE_NAME);
+
+            if path.is_file() {
+                runs.push(serde_json::from_str::<Run>(&fs::read_to_string(path)?)?);
+            }
+        }
+
+        runs.sort_by(|a, b| (a.created, &a.id).cmp(&(b.created, &b.id)));
+
+        Ok(runs)
+    }
+
+    /// Restores the files of the given run, or of the last one.
+    ///
+    /// Files modified after the run are left alone unless `force` is set. The run
+    /// is removed from the journal once every file has been restored.
+    pub fn undo<P: AsRef<Path>>(
+        root: P,
+        id: Option<&str>,
+        force: bool,
+    ) -> Result<Vec<Restored>, Box<dyn Error>> {
+        let root = root.as_ref();
+        let runs = Self::list(root)?;
+
+        let run = match id {
+            Some(id) => runs
+                .into_iter()
+                .find(|run| run.id == id)
+                .ok_or_else(|| format!("there is no run called {id} in {}", root.display()))?,
+            None => runs
+                .into_iter()
+                .last()
+                .ok_or_else(|| format!("there are no runs to undo in {}", root.display()))?,
+        };
+
+        let dir = root.join(&run.id);
+        let mut restored = Vec::new();
+
+        // Restored in reverse, so the oldest backup wins if a file was written twice
+        for entry in run.files.iter().rev() {
+            let current = fs::read(&entry.path).ok();
+
+            if !force
+                && current
+                    .as_deref()
+                    .is_some_and(|current| hash(current) != entry.written_hash)
+            {
+                restored.push(Restored::Modified(entry.path.clone()));
+
+                continue;
+            }
+
+            if let Some(backup) = &entry.backup {
+                crate::write_file(&entry.path, &fs::read(dir.join(backup))?)?;
+            } else if current.is_some() {
+                fs::remove_file(&entry.path)?;
+            }
+
+            restored.push(Restored::Restored(entry.path.clone()));
+        }
+
+        if restored
+            .iter()
+            .all(|restored| matches!(restored, Restored::Restored(_)))
+        {
+            fs::remove_dir_all(dir)?;
+        }
+
+        Ok(restored)
+    }
+}
+
+fn hash(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::test_dir;
+
+    #[test]
+    fn undo_restores_rewritten_and_removes_created_files() {
+        let dir = test_dir("journal-undo");
+        let root = dir.join(".fixer");
+        let rewritten = dir.join("data.lua");
+        let created = dir.join("changelog.txt");
+        let mut journal = Journal::new(&root);
+
+        fs::write(&rewritten, "new").unwrap();
+        journal.record(&rewritten, Some(b"old"), b"new").unwrap();
+        fs::write(&created, "created").unwrap();
+        journal.record(&created, None, b"created").unwrap();
+
+        let restored = Journal::undo(&root, None, false).unwrap();
+
+        assert_eq!(restored.len(), 2);
+        assert_eq!(fs::read_to_string(&rewritten).unwrap(), "old");
+        assert!(!created.exists());
+        assert!(Journal::list(&root).unwrap().is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn undo_keeps_files_modified_after_the_run() {
+        let dir = test_dir("journal-modified");
+        let root = dir.join(".fixer");
+        let path = dir.join("data.lua");
+        let mut journal = Journal::new(&root);
+
+        fs::write(&path, "new").unwrap();
+        journal.record(&path, Some(b"old"), b"new").unwrap();
+        fs::write(&path, "edited by hand").unwrap();
+
+        let restored = Journal::undo(&root, None, false).unwrap();
+
+        assert!(matches!(restored[..], [Restored::Modified(_)]));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "edited by hand");
+        assert_eq!(Journal::list(&root).unwrap().len(), 1);
+
+        Journal::undo(&root, None, true).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,9 +1,7 @@
+use crate::archive;
 use owo_colors::OwoColorize;
-use std::{
-    collections::HashMap,
-    fs,
-    path::{Path, PathBuf},
-};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, error::Error, fs, path::Path};
 
 #[derive(Debug, Default)]
 pub struct Locales {
@@ -63,27 +61,88 @@ impl Locales {
             .map(String::as_str)
     }
 
-    pub fn load_dir<P: AsRef<Path>>(&mut self, path: P) {
-        let path = path.as_ref();
+    /// Hash of every loaded key and value, independent of load order.
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = Sha256::new();
+        let mut categories = self.locales.iter().collect::<Vec<_>>();
+
+        categories.sort_by_key(|(name, _)| *name);
+
+        for (name, category) in categories {
+            let mut entries = category.iter().collect::<Vec<_>>();
+
+            entries.sort();
+
+            hasher.update(format!("[{name}]\n"));
+
+            for (key, value) in entries {
+                hasher.update(format!("{key}={value}\n"));
+            }
+        }
 
-        for file in path.join("en").read_dir().unwrap().filter_map(|file| {
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Loads `<path>/en/*.cfg`, where `path` is the `locale` directory of a mod.
+    pub fn load_dir<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Box<dyn Error>> {
+        let dir = path.as_ref().join("en");
+        let files = dir
+            .read_dir()
+            .map_err(|error| format!("{}: {error}", dir.display()))?;
+
+        for file in files.filter_map(|file| {
             file.ok().map(|value| value.path()).filter(|value| {
                 value.is_file() && value.extension().is_some_and(|ext| ext == "cfg")
             })
         }) {
-            self.load(file);
+            self.load(&file)?;
         }
+
+        Ok(())
     }
 
-    pub fn load(&mut self, path: PathBuf) {
-        println!(
+    pub fn load(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        eprintln!(
             "[{}] Loading locale at {}",
             "Locales".bright_blue(),
             path.display().bright_green()
         );
 
-        let cfg = fs::read_to_string(path).unwrap();
+        let cfg =
+            fs::read_to_string(path).map_err(|error| format!("{}: {error}", path.display()))?;
+
+        self.load_str(&cfg);
+
+        Ok(())
+    }
+
+    /// Loads `locale/en/*.cfg` from a packed mod.
+    pub fn load_zip<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+
+        let files = archive::read_files(path, |file| {
+            let components = file.iter().collect::<Vec<_>>();
+
+            components.len() == 4
+                && components[1] == "locale"
+                && components[2] == "en"
+                && file.extension().is_some_and(|ext| ext == "cfg")
+        })?;
+
+        for file in files {
+            eprintln!(
+                "[{}] Loading locale at {}",
+                "Locales".bright_blue(),
+                path.join(&file.path).display().bright_green()
+            );
+
+            self.load_str(&file.contents);
+        }
+
+        Ok(())
+    }
 
+    pub fn load_str(&mut self, cfg: &str) {
         let mut current = "default";
 
         for value in cfg.split('\n') {
@@ -117,3 +176,27 @@ impl Locales {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn loads_the_english_locale_of_a_directory() {
+        let dir = testing::test_dir("locales-dir");
+
+        fs::create_dir_all(dir.join("locale/en")).unwrap();
+        fs::create_dir_all(dir.join("locale/de")).unwrap();
+        fs::write(dir.join("locale/en/a.cfg"), "[item-name]\nplate=Plate\n").unwrap();
+        fs::write(dir.join("locale/de/a.cfg"), "[item-name]\nplate=Platte\n").unwrap();
+
+        let mut locales = Locales::default();
+
+        locales.load_dir(dir.join("locale")).unwrap();
+
+        assert_eq!(locales.get("item-name", "plate"), Some("Plate"));
+        // A missing directory is an error instead of a panic
+        assert!(locales.load_dir(dir.join("missing")).is_err());
+    }
+}
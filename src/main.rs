@@ -1,176 +1,1688 @@
 #![warn(clippy::all, clippy::nursery, clippy::pedantic)]
 #![allow(clippy::missing_panics_doc, clippy::missing_errors_doc)]
 
+mod archive;
+mod cache;
+mod changelog;
+mod cli;
+mod config;
+mod diagnostics;
+mod diff;
+mod journal;
 mod locales;
+mod prototypes;
+mod report;
 mod rules;
+mod splice;
+mod style;
+#[cfg(test)]
+mod testing;
 mod value;
 
-use full_moon::{ast::TableConstructor, node::Node, visitors::VisitorMut};
+use archive::ArchiveFile;
+use cache::Cache;
+use clap::Parser;
+use cli::{Cli, Command, Detection, Formatting, ReportArgs, ReportFormat};
+use config::Config;
+use diagnostics::{Diagnostics, Severity};
+use full_moon::{
+    ast::{self, TableConstructor},
+    node::Node,
+    visitors::VisitorMut,
+};
+use journal::{Journal, Restored};
 use locales::Locales;
+use owo_colors::OwoColorize;
+use prototypes::PrototypeIndex;
+use rayon::prelude::*;
+use report::Report;
 use rules::{
-    fluid_boxes::FIX_FLUID_BOXES,
-    graphics::{
-        beam::FIX_BEAM_GRAPHICS, hr_version::FIX_HIGH_RES_GRAPHICS, machine::FIX_MACHINE_GRAPHICS,
-        offshore_pump::FIX_OFFSHORE_PUMP_GRAPHICS, turret::FIX_TURRET_GRAPHICS,
-    },
-    recipe::FIX_RECIPE,
-    FixRule,
+    path::{PathSegment, TablePath},
+    Rule, RuleContext, RuleSelection, RuleSet, RunSettings,
 };
 use std::{
+    collections::{BTreeSet, HashMap, HashSet},
     error::Error,
-    fs,
+    fs, io,
     path::{Path, PathBuf},
+    process::ExitCode,
+    thread,
+    time::{Duration, SystemTime},
 };
 pub use value::*;
 
+/// What happens to files that rules changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Write changed files back to disk
+    Write,
+    /// Print a unified diff for every changed file
+    Diff,
+    /// Only record which files would change
+    Check,
+}
+
+/// Where unified diffs are printed in [`Mode::Diff`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DiffOutput {
+    #[default]
+    Stdout,
+    /// Keeps stdout free for a machine-readable report
+    Stderr,
+}
+
 #[derive(Debug)]
 pub struct LuaFixApplier {
     pub name: String,
     pub locales: Locales,
-    pub rules: Vec<FixRule>,
+    /// Every prototype of the run, only built if a rule uses it
+    pub prototypes: PrototypeIndex,
+    /// One instance of every Lua rule, shared by the rule sets. Files are fixed
+    /// by forks that are merged back in path order.
+    pub instances: Vec<Box<dyn Rule>>,
+    /// Rules for mods without an override
+    pub rules: RuleSet,
+    /// Rules for mods that have an override, keyed by mod name
+    pub overrides: HashMap<String, RuleSet>,
+    /// Directory names that are never visited
+    pub exclude: Vec<String>,
+    /// The `[stylua]` section of the configuration
+    pub stylua_defaults: toml::Table,
+    pub formatting: Formatting,
+    pub detection: Detection,
+    pub mode: Mode,
+    pub diff_output: DiffOutput,
+    /// Whether packed mods are rewritten in [`Mode::Write`]
+    pub write_zips: bool,
+    /// Separate tree that receives a copy of every mod in [`Mode::Write`],
+    /// leaving the originals untouched
+    pub out_dir: Option<PathBuf>,
+    /// Whether a changelog section is added to every changed mod directory
+    pub changelog: bool,
+    /// Directory or archive of the mod currently being visited
+    pub root: PathBuf,
+    /// `version` from the info.json of the current mod, after fixing
+    pub version: Option<String>,
+    /// Backups of files rewritten in place
+    pub journal: Option<Journal>,
+    /// Whether the table text around every rule application is recorded
+    pub snippets: bool,
+    /// Files known to be left alone by the rules
+    pub cache: Cache,
+    pub diagnostics: Diagnostics,
 }
 
 impl LuaFixApplier {
-    fn new<T: Into<String>>(name: T) -> Self {
-        let rules = vec![
-            FIX_RECIPE,
-            FIX_BEAM_GRAPHICS,
-            FIX_MACHINE_GRAPHICS,
-            FIX_OFFSHORE_PUMP_GRAPHICS,
-            FIX_TURRET_GRAPHICS,
-            FIX_HIGH_RES_GRAPHICS,
-            FIX_FLUID_BOXES,
-        ];
-
-        Self {
+    fn new<T: Into<String>>(name: T, config: &Config) -> Result<Self, Box<dyn Error>> {
+        config.validate_rules()?;
+
+        Ok(Self {
             name: name.into(),
             locales: Locales::default(),
-            rules,
-        }
+            prototypes: PrototypeIndex::default(),
+            instances: rules::all(),
+            rules: RuleSet::new(&config.selection(None), config.options(None)),
+            overrides: config
+                .overrides
+                .keys()
+                .map(|name| {
+                    (
+                        name.clone(),
+                        RuleSet::new(&config.selection(Some(name)), config.options(Some(name))),
+                    )
+                })
+                .collect(),
+            exclude: config.exclude(),
+            stylua_defaults: config.stylua()?,
+            formatting: config.formatting,
+            detection: config.detection,
+            mode: Mode::Write,
+            diff_output: DiffOutput::Stdout,
+            write_zips: config.write_zips,
+            out_dir: config.out_dir.clone(),
+            changelog: config.changelog,
+            root: PathBuf::new(),
+            version: None,
+            journal: None,
+            snippets: false,
+            cache: Cache::default(),
+            diagnostics: Diagnostics::default(),
+        })
     }
 
     fn set_name<T: Into<String>>(&mut self, name: T) {
         self.name = name.into();
+        self.diagnostics.set_mod(&self.name);
+    }
+
+    /// Fixes every file of the mods at `paths`.
+    fn visit_mods(&mut self, paths: &[PathBuf]) {
+        let mut mods = Vec::new();
+
+        for path in paths {
+            let result = self
+                .load_mod(path)
+                .and_then(|item| Ok((self.collect_mod(&item)?, item)));
+
+            match result {
+                Ok((files, item)) => mods.push((item, files)),
+                Err(error) => self.fail(path, &*error),
+            }
+        }
+
+        self.visit(mods);
+    }
+
+    /// Fixes the files of every mod in parallel, then writes them mod by mod
+    /// in path order so output doesn't depend on scheduling.
+    fn visit(&mut self, mods: Vec<(Mod, ModFiles)>) {
+        let fixed = mods
+            .into_par_iter()
+            .map(|(item, files)| {
+                let fixed = self.fix_mod(&item, files);
+
+                (item, fixed)
+            })
+            .collect::<Vec<_>>();
+
+        for (item, fixed) in fixed {
+            if let Err(error) = self.save_mod(&item, fixed) {
+                self.fail(&item.root, &*error);
+            }
+        }
+    }
+
+    /// Makes the mod at `path` the current one and loads its settings,
+    /// without visiting any files.
+    fn load_mod(&mut self, path: &Path) -> Result<Mod, Box<dyn Error>> {
+        let mut item = Mod {
+            name: cli::mod_name(path),
+            root: path.to_path_buf(),
+            stylua: self.stylua_defaults.clone(),
+        };
+
+        self.enter_mod(&item);
+
+        if let Some(output) = self.output_path(path) {
+            if output.exists() && output.canonicalize()? == path.canonicalize()? {
+                return Err(format!(
+                    "output directory would overwrite {}, choose another one",
+                    path.display()
+                )
+                .into());
+            }
+        }
+
+        // Packed mods can only have a stylua.toml above them
+        let dir = path.canonicalize()?;
+        let dir = if archive::is_mod_zip(path) {
+            dir.parent().unwrap_or(&dir).to_path_buf()
+        } else {
+            dir
+        };
+
+        if let Some(file) = style::find_config(&dir) {
+            item.stylua.extend(style::load_config(&file)?);
+        }
+
+        Ok(item)
+    }
+
+    /// Makes `item` the mod whose files are written.
+    fn enter_mod(&mut self, item: &Mod) {
+        self.set_name(&item.name);
+        self.root.clone_from(&item.root);
+        self.version = None;
+    }
+
+    /// Adds a section with one line per rule that fixed something in the mod
+    /// to its changelog.txt, creating the file if needed.
+    fn write_changelog(&mut self, root: &Path) -> Result<(), Box<dyn Error>> {
+        // Rules may report fixes that format back to the same file
+        if !self
+            .diagnostics
+            .changed()
+            .iter()
+            .any(|(mod_name, _)| mod_name == &self.name)
+        {
+            return Ok(());
+        }
+
+        let rule_set = self.rule_set(&self.name);
+
+        let lines = rule_set
+            .rules
+            .iter()
+            .map(|index| {
+                (
+                    self.instances[*index].id(),
+                    self.instances[*index].changelog(),
+                )
+            })
+            .chain(rule_set.info.iter().map(|rule| (rule.id, rule.changelog)))
+            .filter_map(|(id, line)| Some((id, line?)))
+            .filter(|(id, _)| {
+                self.diagnostics.entries().iter().any(|diagnostic| {
+                    diagnostic.severity == Severity::Fixed
+                        && diagnostic.rule == Some(id)
+                        && diagnostic.mod_name == self.name
+                })
+            })
+            .map(|(_, line)| format!("{line}."))
+            .collect::<Vec<_>>();
+
+        if lines.is_empty() {
+            return Ok(());
+        }
+
+        let path = root.join(changelog::FILE_NAME);
+
+        self.diagnostics.set_file(&path);
+
+        let Some(version) = self.version.clone() else {
+            self.diagnostics
+                .warning("info.json has no version, no changelog section added");
+
+            return Ok(());
+        };
+
+        let existing = if path.is_file() {
+            Some(fs::read_to_string(&path)?)
+        } else {
+            None
+        };
+
+        let section = changelog::section(&version, &changelog::today(), "Changes", &lines);
+
+        let Some(output) =
+            changelog::prepend(existing.as_deref().unwrap_or_default(), &version, &section)
+        else {
+            self.diagnostics.warning(format!(
+                "changelog.txt already has a section for {version}, bump the version to add one"
+            ));
+
+            return Ok(());
+        };
+
+        self.diagnostics.mark_changed();
+
+        match self.mode {
+            Mode::Write => {
+                if let Some(output_path) = self.output_path(&path) {
+                    write_file(&output_path, output.as_bytes())?;
+                } else {
+                    write_file(&path, output.as_bytes())?;
+
+                    if let Some(journal) = &mut self.journal {
+                        journal.record(
+                            &path,
+                            existing.as_deref().map(str::as_bytes),
+                            output.as_bytes(),
+                        )?;
+                    }
+                }
+            }
+            Mode::Diff => {
+                self.print_diff(&path, existing.as_deref().unwrap_or_default(), &output);
+            }
+            Mode::Check => {}
+        }
+
+        Ok(())
+    }
+
+    fn print_diff(&self, path: &Path, original: &str, output: &str) {
+        let diff = diff::unified_diff(path, original, output);
+
+        match self.diff_output {
+            DiffOutput::Stdout => print!("{diff}"),
+            DiffOutput::Stderr => eprint!("{diff}"),
+        }
+    }
+
+    /// Where a file of the current mod goes in the output tree, if there is one.
+    fn output_path(&self, path: &Path) -> Option<PathBuf> {
+        if self.mode != Mode::Write {
+            return None;
+        }
+
+        let out_dir = self.out_dir.as_ref()?;
+        let relative = path.strip_prefix(&self.root).ok()?;
+
+        let output = out_dir.join(self.root.file_name()?);
+
+        if relative.as_os_str().is_empty() {
+            Some(output)
+        } else {
+            Some(output.join(relative))
+        }
+    }
+
+    /// Indexes of the rules enabled for any mod, in the order they are applied.
+    fn enabled_rules(&self) -> BTreeSet<usize> {
+        std::iter::once(&self.rules)
+            .chain(self.overrides.values())
+            .flat_map(|rule_set| rule_set.rules.iter().copied())
+            .collect()
+    }
+
+    /// Whether an enabled rule collects state, which files skipped by the
+    /// cache would be missing from.
+    fn has_stateful_rules(&self) -> bool {
+        self.enabled_rules()
+            .into_iter()
+            .any(|index| self.instances[index].is_stateful())
+    }
+
+    /// Lets every rule report what it collected over the whole run.
+    fn finish_rules(&mut self) {
+        self.diagnostics.set_mod(String::new());
+        self.diagnostics.set_file(PathBuf::new());
+
+        for index in self.enabled_rules() {
+            let rule = &mut self.instances[index];
+
+            self.diagnostics.set_rule(Some(rule.id()));
+
+            rule.finish(&mut self.diagnostics);
+        }
+
+        self.diagnostics.set_rule(None);
+    }
+
+    const fn settings(&self) -> RunSettings {
+        RunSettings {
+            mode: self.mode,
+            formatting: self.formatting,
+            detection: self.detection,
+        }
+    }
+
+    /// Rules that apply to the mod `mod_name`.
+    fn rule_set(&self, mod_name: &str) -> &RuleSet {
+        self.overrides.get(mod_name).unwrap_or(&self.rules)
     }
 
-    fn visit_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Box<dyn Error>> {
-        let path = path.as_ref();
+    /// Applies rules to a single file of `item`. Only reads shared state, so it
+    /// can run on any thread; the result is written by [`Self::save`].
+    fn fix_file(&self, item: &Mod, path: &Path, original: String, info: bool) -> FixedFile {
+        let rule_set = self.rule_set(&item.name);
+        let mut diagnostics = Diagnostics::default();
+        let key = self.cache.key(
+            &format!("{}{}", rule_set.fingerprint(), item.stylua),
+            &original,
+        );
 
-        if path.extension().is_some_and(|ext| ext == "lua") {
-            let file = fs::read_to_string(path)?;
+        diagnostics.set_mod(&item.name);
+        diagnostics.set_file(path);
 
-            if let Ok(ast) = full_moon::parse(&file) {
+        if self.cache.is_clean(path, &key) {
+            return FixedFile {
+                path: path.to_path_buf(),
+                original,
+                output: None,
+                info,
+                key,
+                rules: Vec::new(),
+                diagnostics,
+            };
+        }
+
+        let mut rules = Vec::new();
+        let result = if info {
+            rules::info::apply(&mut diagnostics, rule_set, &original).map_err(Into::into)
+        } else {
+            rules = rule_set
+                .rules
+                .iter()
+                .map(|index| (*index, self.instances[*index].fork()))
+                .collect();
+
+            self.fix_source(item, path, &mut diagnostics, &mut rules, &original)
+        };
+
+        let output = match result {
+            Ok(output) => output,
+            Err(error) => {
+                diagnostics.set_file(path);
+                diagnostics.error(format!("could not be processed: {error}"));
+
+                None
+            }
+        };
+
+        if output.is_some() {
+            diagnostics.mark_changed();
+        }
+
+        FixedFile {
+            path: path.to_path_buf(),
+            original,
+            output,
+            info,
+            key,
+            rules,
+            diagnostics,
+        }
+    }
+
+    /// Applies rules to a Lua source, returns the formatted result if it changed.
+    fn fix_source(
+        &self,
+        item: &Mod,
+        path: &Path,
+        diagnostics: &mut Diagnostics,
+        rules: &mut [(usize, Box<dyn Rule>)],
+        file: &str,
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        match full_moon::parse(file) {
+            Ok(ast) => {
                 let prev_ast = ast.clone();
-                let result_ast = self.visit_ast(ast);
+                let stylua = style::settings(&item.stylua, file)?;
+                let mut fixer = TableFixer {
+                    mod_name: &item.name,
+                    file: path,
+                    locales: &self.locales,
+                    prototypes: &self.prototypes,
+                    rule_set: self.rule_set(&item.name),
+                    rules,
+                    settings: self.settings(),
+                    snippets: self.snippets,
+                    stylua,
+                    minimal: self.formatting == Formatting::Tables,
+                    prototype_tables: prototypes::find(&ast, self.detection),
+                    changed: HashSet::new(),
+                    segments: Vec::new(),
+                    tables: Vec::new(),
+                    diagnostics,
+                };
+                let result_ast = fixer.visit_ast(ast);
 
                 if !prev_ast.similar(&result_ast) {
-                    let mut config = stylua_lib::Config::new();
+                    let output = if fixer.minimal {
+                        let output = splice::splice(file, &result_ast, &fixer.changed, stylua)?;
 
-                    config.indent_type = stylua_lib::IndentType::Spaces;
-                    config.indent_width = 2;
+                        // Splicing is only safe if the result still parses
+                        full_moon::parse(&output).map_err(|errors| {
+                            format!(
+                                "rewritten tables don't parse: {}",
+                                errors
+                                    .first()
+                                    .map(full_moon::Error::error_message)
+                                    .unwrap_or_default()
+                            )
+                        })?;
 
-                    fs::write(
-                        path,
-                        &stylua_lib::format_code(
+                        output
+                    } else {
+                        stylua_lib::format_code(
                             &result_ast.to_string(),
-                            config,
+                            stylua,
                             None,
                             stylua_lib::OutputVerification::Full,
-                        )?,
-                    )?;
+                        )?
+                    };
+
+                    if output != file {
+                        return Ok(Some(output));
+                    }
+                }
+            }
+            Err(errors) => {
+                for error in errors {
+                    diagnostics.push_at(
+                        Severity::Error,
+                        Some(error.range().0),
+                        error.error_message(),
+                    );
                 }
             }
         }
 
+        Ok(None)
+    }
+
+    /// Adds the diagnostics of a fixed file to the run, returns the rewritten
+    /// file if it changed.
+    fn merge(&mut self, fixed: FixedFile) -> Option<String> {
+        if fixed.info {
+            self.version = serde_json::from_str::<serde_json::Value>(
+                fixed.output.as_deref().unwrap_or(&fixed.original),
+            )
+            .ok()
+            .and_then(|info| info.get("version")?.as_str().map(String::from));
+        }
+
+        if self.mode == Mode::Diff {
+            if let Some(output) = &fixed.output {
+                self.print_diff(&fixed.path, &fixed.original, output);
+            }
+        }
+
+        // Only files without diagnostics are cached, so skipping them loses nothing
+        if fixed.output.is_none() && fixed.diagnostics.is_empty() {
+            self.cache.insert(&fixed.path, fixed.key);
+        } else {
+            self.cache.remove(&fixed.path);
+        }
+
+        for (index, fork) in fixed.rules {
+            self.instances[index].merge(fork);
+        }
+
+        self.diagnostics.append(fixed.diagnostics);
+
+        fixed.output
+    }
+
+    /// Writes a file fixed by [`Self::fix_file`], or copies an untouched one to
+    /// the output tree.
+    fn save(&mut self, path: &Path, fixed: Option<FixedFile>) -> Result<(), Box<dyn Error>> {
+        let output_path = self.output_path(path);
+
+        if let Some(fixed) = fixed {
+            let original = fixed.original.clone();
+
+            if let Some(output) = self.merge(fixed) {
+                if self.mode == Mode::Write {
+                    if let Some(output_path) = output_path {
+                        write_file(&output_path, output.as_bytes())?;
+                    } else {
+                        write_file(path, output.as_bytes())?;
+
+                        if let Some(journal) = &mut self.journal {
+                            journal.record(path, Some(original.as_bytes()), output.as_bytes())?;
+                        }
+                    }
+                }
+
+                return Ok(());
+            }
+        }
+
+        if let Some(output_path) = output_path {
+            copy_file(path, &output_path)?;
+        }
+
         Ok(())
     }
 
-    fn visit_dir<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Box<dyn Error>> {
-        let path = path.as_ref();
+    /// Lists the files of `item` that are visited. Packed mods are read right
+    /// away, files of mod directories while they are fixed.
+    fn collect_mod(&mut self, item: &Mod) -> Result<ModFiles, Box<dyn Error>> {
+        if archive::is_mod_zip(&item.root) {
+            let files = archive::read_files(&item.root, |file| {
+                is_info_json(file)
+                    || file.extension().is_some_and(|ext| ext == "lua")
+                        && !file.parent().is_some_and(|dir| {
+                            dir.iter().any(|name| {
+                                self.exclude.iter().any(|exclude| name == exclude.as_str())
+                            })
+                        })
+            })?;
+
+            return Ok(ModFiles::Zip(files));
+        }
+
+        let mut files = Vec::new();
+
+        self.collect_files(&item.root, &mut files)?;
+
+        Ok(ModFiles::Dir(files))
+    }
+
+    /// Fixes the files of `item` in parallel, only reads shared state like
+    /// [`Self::fix_file`].
+    fn fix_mod(&self, item: &Mod, files: ModFiles) -> FixedMod {
+        match files {
+            ModFiles::Zip(files) => FixedMod::Zip(
+                files
+                    .into_par_iter()
+                    .map(|file| {
+                        let info = is_info_json(&file.path);
+
+                        (
+                            file.index,
+                            self.fix_file(item, &item.root.join(&file.path), file.contents, info),
+                        )
+                    })
+                    .collect(),
+            ),
+            ModFiles::Dir(mut files) => {
+                files.sort();
+
+                let info_path = item.root.join(rules::info::FILE_NAME);
+                let fixed = files
+                    .par_iter()
+                    .map(|path| {
+                        let info = path == &info_path;
+
+                        if !info && path.extension().is_none_or(|ext| ext != "lua") {
+                            return Ok(None);
+                        }
+
+                        fs::read_to_string(path)
+                            .map(|original| Some(self.fix_file(item, path, original, info)))
+                    })
+                    .collect::<Vec<_>>();
+
+                FixedMod::Dir(files.into_iter().zip(fixed).collect())
+            }
+        }
+    }
 
+    /// Writes the files of `item` fixed by [`Self::fix_mod`] in path order.
+    fn save_mod(&mut self, item: &Mod, fixed: FixedMod) -> Result<(), Box<dyn Error>> {
+        self.enter_mod(item);
+
+        match fixed {
+            FixedMod::Zip(files) => self.save_zip(&item.root, files),
+            FixedMod::Dir(files) => {
+                for (path, fixed) in files {
+                    let result = fixed
+                        .map_err(Into::into)
+                        .and_then(|fixed| self.save(&path, fixed));
+
+                    if let Err(error) = result {
+                        self.fail(&path, &*error);
+                    }
+                }
+
+                if self.changelog && self.mode != Mode::Check {
+                    self.write_changelog(&item.root)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Rewrites a packed mod with the files that rules changed.
+    fn save_zip(
+        &mut self,
+        path: &Path,
+        files: Vec<(usize, FixedFile)>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut replacements = HashMap::new();
+
+        for (index, fixed) in files {
+            if let Some(output) = self.merge(fixed) {
+                replacements.insert(index, output);
+            }
+        }
+
+        if let Some(output_path) = self.output_path(path) {
+            if replacements.is_empty() {
+                copy_file(path, &output_path)?;
+            } else {
+                archive::rewrite(path, &output_path, &replacements)?;
+            }
+        } else if self.mode == Mode::Write && self.write_zips && !replacements.is_empty() {
+            let original = fs::read(path)?;
+
+            archive::rewrite(path, path, &replacements)?;
+
+            if let Some(journal) = &mut self.journal {
+                journal.record(path, Some(&original), &fs::read(path)?)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lists the files of `path` that are visited, excluded directories are
+    /// copied to the output tree right away.
+    fn collect_files(
+        &mut self,
+        path: &Path,
+        files: &mut Vec<PathBuf>,
+    ) -> Result<(), Box<dyn Error>> {
         for entry in path.read_dir()?.flatten() {
             let path = entry.path();
 
-            if path.is_dir() && !path.ends_with("graphics") && !path.ends_with("locale") {
-                self.visit_dir(path)?;
+            let result = if !path.is_dir() {
+                files.push(path);
+
+                continue;
+            } else if !self.is_excluded(&path) {
+                self.collect_files(&path, files)
+            } else if let Some(output_path) = self.output_path(&path) {
+                copy_dir(&path, &output_path)
             } else {
-                self.visit_file(path)?;
+                Ok(())
+            };
+
+            if let Err(error) = result {
+                self.fail(&path, &*error);
             }
         }
 
         Ok(())
     }
 
-    fn try_visit_table(&self, mut node: Table) -> Option<Table> {
-        for rule in &self.rules {
-            if rule.enabled {
-                if rule.kind.is_none() {
-                    if rule.enabled && (rule.filter)("table", &self.locales, &node) {
-                        (rule.action)(&self.name, "table", &self.locales, &mut node)?;
-                    }
-                } else {
-                    let kind: String = node.get_value("type")?;
-                    let prototype_name: String = node.get_value("name")?;
-
-                    if rule.enabled
-                        && rule.kind.verify(&kind)
-                        && (rule.filter)(&prototype_name, &self.locales, &node)
-                    {
-                        (rule.action)(&self.name, &prototype_name, &self.locales, &mut node)?;
-                    }
-                }
+    /// Records a file or directory that couldn't be processed, so the run can
+    /// carry on with the rest and report every failure at the end.
+    fn fail(&mut self, path: &Path, error: &dyn Error) {
+        self.diagnostics.set_file(path);
+        self.diagnostics
+            .error(format!("could not be processed: {error}"));
+    }
+
+    /// Loads the English locale in the `locale` directory `path`, reporting
+    /// it under `mod_name` if it can't be read.
+    fn load_locale_dir(&mut self, mod_name: &str, path: &Path) {
+        if let Err(error) = self.locales.load_dir(path) {
+            self.diagnostics.set_mod(mod_name);
+            self.fail(path, &*error);
+        }
+    }
+
+    fn is_excluded(&self, path: &Path) -> bool {
+        path.file_name()
+            .is_some_and(|name| self.exclude.iter().any(|exclude| name == exclude.as_str()))
+    }
+
+    /// Every file below `path` outside excluded directories, or `path` itself
+    /// if it is a file. Unreadable directories are skipped.
+    fn walk_files(&self, path: &Path, files: &mut Vec<PathBuf>) {
+        if !path.is_dir() {
+            files.push(path.to_path_buf());
+
+            return;
+        }
+
+        for entry in path.read_dir().into_iter().flatten().flatten() {
+            let path = entry.path();
+
+            if !path.is_dir() || !self.is_excluded(&path) {
+                self.walk_files(&path, files);
             }
         }
+    }
+
+    /// Whether an enabled rule looks at other prototypes.
+    fn uses_prototypes(&self) -> bool {
+        self.enabled_rules()
+            .into_iter()
+            .any(|index| self.instances[index].uses_prototypes())
+    }
+
+    /// Indexes the prototypes of every mod, so rules can look up the ones
+    /// defined in other files. Files that can't be read are left out here and
+    /// reported when they are fixed.
+    fn load_prototypes(&mut self, mods: &[PathBuf]) {
+        for root in mods {
+            let name = cli::mod_name(root);
+            let files = if archive::is_mod_zip(root) {
+                archive::read_files(root, |file| {
+                    file.extension().is_some_and(|ext| ext == "lua")
+                })
+                .map(|files| {
+                    files
+                        .into_iter()
+                        .map(|file| (root.join(file.path), file.contents))
+                        .collect()
+                })
+                .unwrap_or_default()
+            } else {
+                let mut files = Vec::new();
+
+                self.walk_files(root, &mut files);
 
-        Some(node)
+                files.sort();
+
+                files
+                    .into_iter()
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "lua"))
+                    .filter_map(|path| fs::read_to_string(&path).ok().map(|source| (path, source)))
+                    .collect::<Vec<_>>()
+            };
+
+            let index = files
+                .into_par_iter()
+                .map(|(path, source)| {
+                    let mut index = PrototypeIndex::default();
+
+                    index.load_source(&name, &path, &source, self.detection);
+
+                    index
+                })
+                .reduce(PrototypeIndex::default, |mut index, other| {
+                    index.extend(other);
+
+                    index
+                });
+
+            self.prototypes.extend(index);
+        }
     }
 }
 
-impl VisitorMut for LuaFixApplier {
-    fn visit_table_constructor(&mut self, node: TableConstructor) -> TableConstructor {
-        if let Some(table) = self.try_visit_table(Table::new(&node)) {
-            return table.into_constructor();
+/// A mod of the run and the settings its files are fixed with.
+#[derive(Debug)]
+struct Mod {
+    name: String,
+    /// Directory or archive of the mod
+    root: PathBuf,
+    /// Stylua settings, the mod's own `stylua.toml` on top of the defaults.
+    /// Indentation left unset is detected for every file.
+    stylua: toml::Table,
+}
+
+/// Files of a mod waiting to be fixed.
+#[derive(Debug)]
+enum ModFiles {
+    /// Files of a mod directory, read while they are fixed
+    Dir(Vec<PathBuf>),
+    /// Lua files and the info.json of a packed mod
+    Zip(Vec<ArchiveFile>),
+}
+
+/// Files of a mod fixed by [`LuaFixApplier::fix_mod`], waiting to be written.
+#[derive(Debug)]
+enum FixedMod {
+    /// Every file of the directory in path order, `None` for files that no
+    /// rule looks at
+    Dir(Vec<(PathBuf, io::Result<Option<FixedFile>>)>),
+    /// Fixed files with their index in the archive
+    Zip(Vec<(usize, FixedFile)>),
+}
+
+/// A file fixed by [`LuaFixApplier::fix_file`], waiting to be written.
+#[derive(Debug)]
+struct FixedFile {
+    path: PathBuf,
+    original: String,
+    /// The rewritten file, `None` if no rule changed it
+    output: Option<String>,
+    /// Whether this is the info.json of the mod
+    info: bool,
+    /// Cache key of the original contents
+    key: String,
+    /// Forks of the rules that fixed the file, with their index in
+    /// [`LuaFixApplier::instances`]
+    rules: Vec<(usize, Box<dyn Rule>)>,
+    diagnostics: Diagnostics,
+}
+
+/// Applies rules to the tables of a single file.
+struct TableFixer<'a> {
+    mod_name: &'a str,
+    file: &'a Path,
+    locales: &'a Locales,
+    prototypes: &'a PrototypeIndex,
+    rule_set: &'a RuleSet,
+    /// Forks of the enabled rules, only used for this file
+    rules: &'a mut [(usize, Box<dyn Rule>)],
+    settings: RunSettings,
+    /// Whether the table text around every rule application is recorded
+    snippets: bool,
+    /// Formatting of the mod, snippets are recorded the way they would be written
+    stylua: stylua_lib::Config,
+    /// Whether changed tables are recorded in `changed`
+    minimal: bool,
+    /// Byte offsets of the opening braces of tables that define prototypes,
+    /// with the `data.raw` keys they were assigned to
+    prototype_tables: HashMap<usize, Option<(String, String)>>,
+    /// Byte offsets of the opening braces of tables that rules changed
+    changed: HashSet<usize>,
+    /// Keys leading to the field being visited, from the outermost table
+    segments: Vec<PathSegment>,
+    /// Tables being visited, innermost last
+    tables: Vec<OpenTable>,
+    diagnostics: &'a mut Diagnostics,
+}
+
+/// A table whose fields are being visited.
+#[derive(Debug, Default)]
+struct OpenTable {
+    /// Positional fields seen so far
+    positional: usize,
+    /// `type` and `name` if the table is a prototype, with the length of
+    /// `segments` when it was entered
+    prototype: Option<(usize, String, String)>,
+}
+
+impl TableFixer<'_> {
+    /// Applies every rule to the table, a rule that fails leaves the table as
+    /// the previous rules left it.
+    fn visit_table(&mut self, mut node: Table) -> Table {
+        let rule_set = self.rule_set;
+        let prototype = node
+            .start_position()
+            .and_then(|position| self.prototype_tables.get(&position.bytes()));
+        // Tables that only look like prototypes, like `results` entries, have neither
+        let (kind, name) = prototype.map_or((None, None), |keys| {
+            prototypes::literal_or_keys(&node, keys.as_ref())
+        });
+        let path = self.enter_table(kind.as_deref(), name.as_deref());
+        // Nested tables are reported under the prototype they are part of
+        let (prototype_kind, prototype_name) = path.prototype.clone().unzip();
+
+        self.diagnostics
+            .set_prototype(prototype_kind, prototype_name, node.start_position());
+
+        for (_, rule) in self.rules.iter_mut() {
+            if !rule.kind().is_none()
+                && (name.is_none() || !kind.as_deref().is_some_and(|kind| rule.kind().verify(kind)))
+            {
+                continue;
+            }
+
+            let id = rule.id();
+
+            self.diagnostics.set_rule(Some(id));
+
+            let since = self.diagnostics.len();
+            let mut context = RuleContext {
+                mod_name: self.mod_name,
+                file: self.file,
+                kind: kind.as_deref(),
+                name: name.as_deref(),
+                path: &path,
+                locales: self.locales,
+                prototypes: self.prototypes,
+                options: &rule_set.options[id],
+                settings: self.settings,
+                diagnostics: self.diagnostics,
+            };
+
+            if !rule.check(&context, &node) {
+                continue;
+            }
+
+            let before = node.clone();
+            let result = rule.apply(&mut context, &mut node);
+
+            if let Err(error) = result {
+                error.report(self.diagnostics);
+
+                node = before.clone();
+            }
+
+            if self.snippets {
+                let (before, after) = (snippet(&before, self.stylua), snippet(&node, self.stylua));
+
+                self.diagnostics.set_snippets(since, &before, &after);
+            }
         }
 
         node
     }
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let root = PathBuf::from("/run/media/aiving/Drive/factorio");
-    let data = root.join("data");
-    let mods = root.join("mods");
+/// Text of `table` formatted on its own, the text as it is if it doesn't format.
+fn snippet(table: &Table, stylua: stylua_lib::Config) -> String {
+    let text = table.to_string();
+
+    splice::format_table(text.trim(), stylua).unwrap_or(text)
+}
+
+/// Whether an archive entry is the info.json of the mod, `<mod>/info.json`.
+fn is_info_json(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name == rules::info::FILE_NAME)
+        && path.components().count() == 2
+}
 
-    let mut visitor = LuaFixApplier::new("Factorio");
+/// Path next to `path` that a file is written to before being renamed over it.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
 
-    visitor.locales.load_dir(data.join("base/locale"));
-    visitor.locales.load_dir(data.join("core/locale"));
-    visitor.locales.load_dir(data.join("quality/locale"));
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".tmp");
 
-    for entry in mods.read_dir()?.flatten() {
+    path.with_file_name(name)
+}
+
+/// Writes through a temporary file and a rename, so an interrupted run never
+/// leaves a half-written file behind.
+fn write_file(path: &Path, contents: &[u8]) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let temp = temp_path(path);
+
+    if let Err(error) = fs::write(&temp, contents).and_then(|()| fs::rename(&temp, path)) {
+        let _ = fs::remove_file(temp);
+
+        return Err(error.into());
+    }
+
+    Ok(())
+}
+
+fn copy_file(from: &Path, to: &Path) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let temp = temp_path(to);
+
+    if let Err(error) = fs::copy(from, &temp).and_then(|_| fs::rename(&temp, to)) {
+        let _ = fs::remove_file(temp);
+
+        return Err(error.into());
+    }
+
+    Ok(())
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<(), Box<dyn Error>> {
+    for entry in from.read_dir()?.flatten() {
         let path = entry.path();
+        let target = to.join(entry.file_name());
 
-        if path.join("locale").is_dir() {
-            visitor.locales.load_dir(path.join("locale"));
+        if path.is_dir() {
+            copy_dir(&path, &target)?;
+        } else {
+            copy_file(&path, &target)?;
         }
     }
 
-    for entry in PathBuf::from("/run/media/aiving/Drive/factorio-dev/AngelsMods")
-        .read_dir()?
-        .flatten()
-    {
-        let path = entry.path();
+    Ok(())
+}
+
+impl TableFixer<'_> {
+    /// Opens a table, returns where it sits.
+    fn enter_table(&mut self, kind: Option<&str>, name: Option<&str>) -> TablePath {
+        let mut table = OpenTable::default();
+
+        if let (Some(kind), Some(name)) = (kind, name) {
+            table.prototype = Some((self.segments.len(), kind.to_string(), name.to_string()));
+        }
+
+        let enclosing = table.prototype.as_ref().or_else(|| {
+            self.tables
+                .iter()
+                .rev()
+                .find_map(|table| table.prototype.as_ref())
+        });
+
+        let path = enclosing.map_or_else(
+            || TablePath {
+                prototype: None,
+                segments: self.segments.clone(),
+            },
+            |(start, kind, name)| TablePath {
+                prototype: Some((kind.clone(), name.clone())),
+                segments: self.segments[*start..].to_vec(),
+            },
+        );
+
+        self.tables.push(table);
+
+        path
+    }
+}
+
+impl VisitorMut for TableFixer<'_> {
+    fn visit_field(&mut self, node: ast::Field) -> ast::Field {
+        let position = self.tables.last_mut().map_or(0, |table| {
+            if matches!(node, ast::Field::NoKey(_)) {
+                table.positional += 1;
+            }
 
-        if path.is_dir() && path.join("info.json").is_file() {
-            visitor.set_name(
-                path.file_name()
-                    .map(|name| name.to_string_lossy().to_string())
-                    .unwrap(),
+            table.positional
+        });
+
+        self.segments.push(PathSegment::of_field(&node, position));
+
+        node
+    }
+
+    fn visit_field_end(&mut self, node: ast::Field) -> ast::Field {
+        self.segments.pop();
+
+        node
+    }
+
+    fn visit_table_constructor_end(&mut self, node: TableConstructor) -> TableConstructor {
+        self.tables.pop();
+
+        node
+    }
+
+    fn visit_table_constructor(&mut self, node: TableConstructor) -> TableConstructor {
+        let table = self.visit_table(Table::new(&node)).into_constructor();
+
+        if self.minimal {
+            splice::mark_changed(&node, &table, &mut self.changed);
+        }
+
+        table
+    }
+}
+
+fn main() -> Result<ExitCode, Box<dyn Error>> {
+    let cli = Cli::parse();
+    let mut config = Config::discover(cli.config.as_deref())?;
+
+    match cli.command {
+        Command::Fix {
+            args,
+            dry_run,
+            out_dir,
+            changelog,
+        } => {
+            config.apply_args(&args);
+
+            if changelog {
+                config.changelog = true;
+            }
+
+            if out_dir.is_some() {
+                config.out_dir = out_dir;
+            }
+
+            let diagnostics = run(
+                &config,
+                &args.report,
+                if dry_run { Mode::Diff } else { Mode::Write },
+            )?;
+
+            Ok(if diagnostics.count(Severity::Error) == 0 {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            })
+        }
+        Command::Check(args) => {
+            config.apply_args(&args);
+
+            let diagnostics = run(&config, &args.report, Mode::Check)?;
+            let changed = diagnostics.changed().len();
+            let errors = diagnostics.count(Severity::Error);
+
+            if changed == 0 && errors == 0 {
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            eprint!("{}", diagnostics.render_changes());
+            eprintln!(
+                "{} {changed} files would be changed by the fixer, {errors} errors",
+                "check failed:".bright_red()
             );
 
-            visitor.visit_dir(path)?;
+            Ok(ExitCode::FAILURE)
+        }
+        Command::Watch { args, interval } => {
+            config.apply_args(&args);
+
+            watch(&config, &args.report, Duration::from_millis(interval))
+        }
+        Command::ListRules(args) => {
+            config.cli_rules = args.rules.selection();
+            config.validate_rules()?;
+
+            list_rules(&config.selection(args.mod_name.as_deref()));
+
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Undo { run, list, force } => undo(&config, run.as_deref(), list, force),
+    }
+}
+
+fn list_rules(selection: &RuleSelection) {
+    let rules = rules::all()
+        .into_iter()
+        .map(|rule| (rule.id(), rule.description(), rule.enabled()));
+    let info = rules::info::all()
+        .into_iter()
+        .map(|rule| (rule.id, rule.description, rule.enabled));
+
+    for (id, description, enabled) in rules.chain(info) {
+        if selection.is_enabled(id, enabled) {
+            println!("{} {}", "[x]".bright_green(), id.bright_blue());
+        } else {
+            println!("[ ] {}", id.bright_blue());
+        }
+
+        println!("    {description}");
+    }
+}
+
+fn undo(
+    config: &Config,
+    run: Option<&str>,
+    list: bool,
+    force: bool,
+) -> Result<ExitCode, Box<dyn Error>> {
+    let journal = config.journal_dir();
+
+    if list {
+        for run in Journal::list(&journal)? {
+            println!("{} ({} files)", run.id.bright_blue(), run.files.len());
+
+            for entry in &run.files {
+                println!("    {}", entry.path.display());
+            }
+        }
+
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let mut modified = 0;
+
+    for restored in Journal::undo(&journal, run, force)? {
+        match restored {
+            Restored::Restored(path) => {
+                println!("{} {}", "restored".bright_green(), path.display());
+            }
+            Restored::Modified(path) => {
+                modified += 1;
+
+                println!(
+                    "{} {}: modified after the run, use --force to restore anyway",
+                    "skipped".bright_yellow(),
+                    path.display()
+                );
+            }
         }
     }
 
+    Ok(if modified == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn print_report(diagnostics: &Diagnostics, report: &ReportArgs) -> Result<(), Box<dyn Error>> {
+    let output = match report.format {
+        ReportFormat::Text => diagnostics.render(),
+        ReportFormat::Json => Report::new(diagnostics).to_json()? + "\n",
+    };
+
+    if let Some(path) = &report.report_file {
+        fs::write(path, output)?;
+    } else {
+        print!("{output}");
+    }
+
     Ok(())
 }
+
+/// Re-runs the rules in dry-run mode on every file that changed since the last
+/// check, until interrupted.
+fn watch(
+    config: &Config,
+    report: &ReportArgs,
+    interval: Duration,
+) -> Result<ExitCode, Box<dyn Error>> {
+    let mut visitor = load_applier(config, Mode::Diff)?;
+    let mods = cli::find_mods(&config.mods)?;
+    let mut previous = HashMap::<PathBuf, HashMap<PathBuf, SystemTime>>::new();
+
+    visitor.snippets = report.format == ReportFormat::Json;
+    visitor.diff_output = report.diff_output();
+    // Only changed files are fixed, a changelog section would miss the rest
+    visitor.changelog = false;
+
+    eprintln!(
+        "{} {} mods, press Ctrl-C to stop",
+        "Watching".bright_blue(),
+        mods.len()
+    );
+
+    loop {
+        let mut changed = 0;
+        let mut visited = Vec::new();
+
+        for root in &mods {
+            let mut times = HashMap::new();
+
+            modified_times(&visitor, root, &mut times);
+
+            let files = times
+                .iter()
+                .filter(|(path, time)| {
+                    previous.get(root).and_then(|previous| previous.get(*path)) != Some(*time)
+                })
+                .map(|(path, _)| path.clone())
+                .collect::<Vec<_>>();
+
+            previous.insert(root.clone(), times);
+
+            if files.is_empty() {
+                continue;
+            }
+
+            // Every report only covers the files changed since the last one
+            if changed == 0 {
+                visitor.diagnostics = Diagnostics::default();
+                visitor.instances = rules::all();
+            }
+
+            changed += files.len();
+
+            let result = visitor.load_mod(root).and_then(|item| {
+                let files = if archive::is_mod_zip(root) {
+                    visitor.collect_mod(&item)?
+                } else {
+                    ModFiles::Dir(files)
+                };
+
+                Ok((item, files))
+            });
+
+            match result {
+                Ok(visit) => visited.push(visit),
+                Err(error) => visitor.fail(root, &*error),
+            }
+        }
+
+        visitor.visit(visited);
+
+        if changed > 0 {
+            visitor.finish_rules();
+
+            print_report(&visitor.diagnostics, report)?;
+
+            eprintln!(
+                "{} {changed} changed files checked, waiting for changes",
+                "Watching".bright_blue()
+            );
+        }
+
+        thread::sleep(interval);
+    }
+}
+
+/// Modification time of every visited file of the mod at `path`.
+fn modified_times(visitor: &LuaFixApplier, path: &Path, times: &mut HashMap<PathBuf, SystemTime>) {
+    let mut files = Vec::new();
+
+    visitor.walk_files(path, &mut files);
+
+    for path in files {
+        if let Ok(time) = path.metadata().and_then(|metadata| metadata.modified()) {
+            times.insert(path, time);
+        }
+    }
+}
+
+/// Creates the applier for `config` and loads every locale it uses.
+fn load_applier(config: &Config, mode: Mode) -> Result<LuaFixApplier, Box<dyn Error>> {
+    if config.mods.is_empty() {
+        return Err(format!(
+            "no mods to process, pass them as arguments or list them in {}",
+            Config::FILE_NAME
+        )
+        .into());
+    }
+
+    let mut visitor = LuaFixApplier::new("Factorio", config)?;
+
+    visitor.mode = mode;
+
+    if let Some(data) = config.data_dir() {
+        for name in ["base", "core", "quality"] {
+            let path = data.join(name).join("locale");
+
+            if path.is_dir() {
+                visitor.load_locale_dir("Factorio", &path);
+            }
+        }
+    }
+
+    if let Some(mods_dir) = config.mods_dir().filter(|path| path.is_dir()) {
+        for entry in mods_dir.read_dir()?.flatten() {
+            let path = entry.path();
+
+            // Mods without an English locale have nothing the rules could use
+            if path.join("locale").is_dir() {
+                if path.join("locale").join("en").is_dir() {
+                    visitor.load_locale_dir(&cli::mod_name(&path), &path.join("locale"));
+                }
+            } else if archive::is_mod_zip(&path) {
+                if let Err(error) = visitor.locales.load_zip(&path) {
+                    visitor.diagnostics.set_mod(cli::mod_name(&path));
+                    visitor.fail(&path, &*error);
+                }
+            }
+        }
+    }
+
+    for path in &config.locales {
+        visitor.load_locale_dir(&cli::mod_name(path), path);
+    }
+
+    if visitor.uses_prototypes() {
+        visitor.load_prototypes(&cli::find_mods(&config.mods)?);
+    }
+
+    Ok(visitor)
+}
+
+fn run(config: &Config, report: &ReportArgs, mode: Mode) -> Result<Diagnostics, Box<dyn Error>> {
+    let mut visitor = load_applier(config, mode)?;
+
+    if mode == Mode::Write && config.out_dir.is_none() {
+        visitor.journal = Some(Journal::new(config.journal_dir()));
+    }
+    visitor.snippets = report.format == ReportFormat::Json;
+    visitor.diff_output = report.diff_output();
+
+    // Stateful rules need to see every file
+    if let Some(path) = config
+        .cache_file()
+        .filter(|_| !visitor.has_stateful_rules())
+    {
+        visitor.cache = Cache::load(
+            path,
+            &format!(
+                "{}\n{:?} {:?}\n{}",
+                visitor.locales.fingerprint(),
+                visitor.formatting,
+                visitor.detection,
+                visitor.prototypes.fingerprint()
+            ),
+        );
+    }
+
+    visitor.visit_mods(&cli::find_mods(&config.mods)?);
+
+    visitor.finish_rules();
+
+    print_report(&visitor.diagnostics, report)?;
+
+    // The files are fixed already, the next run only has to check them again
+    if let Err(error) = std::mem::take(&mut visitor.cache).save() {
+        eprintln!(
+            "{} the cache could not be saved: {error}",
+            "warning:".bright_yellow()
+        );
+    }
+
+    let failures = visitor.diagnostics.count(Severity::Error);
+
+    if failures > 0 {
+        eprint!("{}", visitor.diagnostics.render_failures());
+        eprintln!(
+            "{} {failures} errors, the files above were left unchanged",
+            "failed:".bright_red()
+        );
+    }
+
+    if let Some(journal) = visitor
+        .journal
+        .filter(|journal| !journal.run().files.is_empty())
+    {
+        eprintln!(
+            "Backups of {} files saved as run {}, restore them with `undo`",
+            journal.run().files.len(),
+            journal.run().id.bright_blue()
+        );
+    }
+
+    Ok(visitor.diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rules::{PrototypeKind, RuleOptions, RuleResult};
+    use std::any::Any;
+
+    /// Records the prototype of every recipe table it is applied to.
+    #[derive(Debug, Default)]
+    struct SeenRecipes {
+        seen: Vec<String>,
+    }
+
+    impl Rule for SeenRecipes {
+        fn id(&self) -> &'static str {
+            "test.seen-recipes"
+        }
+
+        fn description(&self) -> &'static str {
+            "Records recipes"
+        }
+
+        fn kind(&self) -> &PrototypeKind {
+            &PrototypeKind::Single("recipe")
+        }
+
+        fn check(&mut self, _context: &RuleContext, _table: &Table) -> bool {
+            true
+        }
+
+        fn apply(&mut self, context: &mut RuleContext, _table: &mut Table) -> RuleResult {
+            self.seen.push(context.path.to_string());
+
+            Ok(())
+        }
+
+        fn fork(&self) -> Box<dyn Rule> {
+            Box::new(Self::default())
+        }
+
+        fn is_stateful(&self) -> bool {
+            true
+        }
+
+        fn merge(&mut self, fork: Box<dyn Rule>) {
+            if let Ok(fork) = (fork as Box<dyn Any>).downcast::<Self>() {
+                self.seen.extend(fork.seen);
+            }
+        }
+    }
+
+    /// Fixes `source` with only `rule` enabled, `rule` is the last instance.
+    fn fix_with(rule: Box<dyn Rule>, source: &str) -> LuaFixApplier {
+        let mut applier = LuaFixApplier::new("test", &Config::default()).unwrap();
+        let id = rule.id();
+
+        applier.rules = RuleSet::new(
+            &RuleSelection::new(Vec::new(), vec!["*".to_string()]),
+            HashMap::new(),
+        );
+        applier.instances.push(rule);
+        applier.rules.rules.push(applier.instances.len() - 1);
+        applier.rules.options.insert(id, RuleOptions::default());
+
+        let item = Mod {
+            name: "mymod".to_string(),
+            root: PathBuf::from("mymod"),
+            stylua: toml::Table::new(),
+        };
+        let fixed = applier.fix_file(
+            &item,
+            Path::new("mymod/data.lua"),
+            source.to_string(),
+            false,
+        );
+
+        applier.merge(fixed);
+
+        applier
+    }
+
+    #[test]
+    fn rules_see_prototypes_assigned_to_data_raw() {
+        let source = r#"
+            data.raw.recipe["x"] = { type = "recipe", name = "x", results = {} }
+            data.raw.recipe.y = { results = { { type = "item", name = "y", amount = 1 } } }
+            data.raw.item.z = { stack_size = 50 }
+        "#;
+        let rule = (fix_with(Box::new(SeenRecipes::default()), source)
+            .instances
+            .pop()
+            .unwrap() as Box<dyn Any>)
+            .downcast::<SeenRecipes>()
+            .unwrap();
+
+        assert_eq!(rule.seen, ["recipe/x", "recipe/y"]);
+    }
+
+    #[test]
+    fn fluid_boxes_are_only_fixed_when_a_connection_moves() {
+        let source = r#"
+            data:extend({
+                {
+                    type = "pump",
+                    name = "aligned",
+                    collision_box = { { -0.4, -0.9 }, { 0.4, 0.9 } },
+                    fluid_box = { pipe_connections = { { position = { 0, -1.5 } } } },
+                },
+                {
+                    type = "pump",
+                    name = "shifted",
+                    collision_box = { { -0.4, -0.9 }, { 0.4, 0.9 } },
+                    fluid_box = { pipe_connections = { { position = { 0, -1.2 } } } },
+                },
+            })
+        "#;
+        let applier = fix_with(Box::new(rules::fluid_boxes::FIX_FLUID_BOXES), source);
+        let names = |severity| {
+            applier
+                .diagnostics
+                .entries()
+                .iter()
+                .filter(|diagnostic| diagnostic.severity == severity)
+                .filter_map(|diagnostic| diagnostic.name.as_deref())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names(Severity::Fixed), ["shifted"]);
+        assert_eq!(names(Severity::Skipped), ["aligned"]);
+    }
+
+    #[test]
+    fn changelog_lists_the_changelog_lines_of_fixing_rules() {
+        let dir = testing::test_dir("changelog-lines");
+        let root = dir.join("mymod");
+
+        fs::create_dir(&root).unwrap();
+        fs::write(
+            root.join("info.json"),
+            r#"{"name": "mymod", "version": "1.0.0", "factorio_version": "1.1"}"#,
+        )
+        .unwrap();
+
+        let config = toml::from_str::<Config>(
+            r#"
+            changelog = true
+
+            [rules]
+            enable = ["info.factorio-version", "info.version"]
+            "#,
+        )
+        .unwrap();
+        let mut applier = LuaFixApplier::new("test", &config).unwrap();
+
+        applier.visit_mods(std::slice::from_ref(&root));
+
+        let changelog = fs::read_to_string(root.join(changelog::FILE_NAME)).unwrap();
+
+        assert!(
+            changelog.contains("Updated `factorio_version` to 2.0."),
+            "{changelog}"
+        );
+        // The version bump is what the section is named after
+        assert!(!changelog.contains("Bump"), "{changelog}");
+        assert_eq!(changelog.matches("\n    - ").count(), 1, "{changelog}");
+    }
+}
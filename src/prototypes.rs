@@ -0,0 +1,411 @@
+use crate::{cli::Detection, diagnostics::Location, Table, Value};
+use full_moon::{
+    ast::{
+        self, Assignment, Call, Expression, FunctionArgs, FunctionCall, Index, Prefix, Suffix,
+        TableConstructor, Var,
+    },
+    visitors::Visitor,
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// Where a prototype is defined.
+#[derive(Debug, Clone)]
+pub struct Prototype {
+    pub mod_name: String,
+    pub file: PathBuf,
+    pub location: Option<Location>,
+}
+
+/// Every prototype defined by the mods of a run, keyed by `type` and `name`.
+#[derive(Debug, Default)]
+pub struct PrototypeIndex {
+    prototypes: HashMap<String, HashMap<String, Prototype>>,
+}
+
+impl PrototypeIndex {
+    pub fn get(&self, kind: &str, name: &str) -> Option<&Prototype> {
+        self.prototypes.get(kind)?.get(name)
+    }
+
+    pub fn contains(&self, kind: &str, name: &str) -> bool {
+        self.get(kind, name).is_some()
+    }
+
+    /// Types of every prototype called `name`, e.g. an item and its recipe.
+    pub fn kinds_of(&self, name: &str) -> Vec<&str> {
+        let mut kinds = self
+            .prototypes
+            .iter()
+            .filter(|(_, prototypes)| prototypes.contains_key(name))
+            .map(|(kind, _)| kind.as_str())
+            .collect::<Vec<_>>();
+
+        kinds.sort_unstable();
+
+        kinds
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prototypes.is_empty()
+    }
+
+    /// Adds every prototype in `source` with a literal `type` and `name`,
+    /// later definitions replace earlier ones like they do in the game.
+    pub fn load_source(&mut self, mod_name: &str, file: &Path, source: &str, detection: Detection) {
+        let Ok(ast) = full_moon::parse(source) else {
+            return;
+        };
+
+        let mut collector = Collector {
+            tables: find(&ast, detection),
+            prototypes: Vec::new(),
+        };
+
+        collector.visit_ast(&ast);
+
+        for (kind, name, location) in collector.prototypes {
+            self.prototypes.entry(kind).or_default().insert(
+                name,
+                Prototype {
+                    mod_name: mod_name.to_string(),
+                    file: file.to_path_buf(),
+                    location,
+                },
+            );
+        }
+    }
+
+    /// Adds every prototype of `other`, replacing ours with the same type and name.
+    pub fn extend(&mut self, other: Self) {
+        for (kind, prototypes) in other.prototypes {
+            self.prototypes.entry(kind).or_default().extend(prototypes);
+        }
+    }
+
+    /// Every `type/name`, sorted, for caching results that depend on the index.
+    pub fn fingerprint(&self) -> String {
+        let mut prototypes = self
+            .prototypes
+            .iter()
+            .flat_map(|(kind, prototypes)| {
+                prototypes.keys().map(move |name| format!("{kind}/{name}"))
+            })
+            .collect::<Vec<_>>();
+
+        prototypes.sort_unstable();
+
+        prototypes.join("\n")
+    }
+}
+
+struct Collector {
+    tables: HashMap<usize, Option<(String, String)>>,
+    prototypes: Vec<(String, String, Option<Location>)>,
+}
+
+impl Visitor for Collector {
+    fn visit_table_constructor(&mut self, node: &TableConstructor) {
+        let Some(keys) = self.tables.get(&brace_offset(node)) else {
+            return;
+        };
+
+        let table = Table::new(node);
+        let (kind, name) = literal_or_keys(&table, keys.as_ref());
+
+        if let (Some(kind), Some(name)) = (kind, name) {
+            self.prototypes
+                .push((kind, name, table.start_position().map(Location::from)));
+        }
+    }
+}
+
+/// Literal key of `.key` or `["key"]`.
+fn index_key(index: &Index) -> Option<String> {
+    match index {
+        Index::Dot { name, .. } => Some(name.to_string().trim().to_string()),
+        Index::Brackets { expression, .. } => match Value::from_raw(expression.clone()) {
+            Some(Value::String(key)) => Some(key),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Byte offset of the opening brace, identifies a table of a parsed file.
+pub fn brace_offset(table: &TableConstructor) -> usize {
+    table.braces().tokens().0.token().start_position().bytes()
+}
+
+/// `type` and `name` of a prototype table, taken from the `data.raw` keys it
+/// was assigned to if the table doesn't set them itself.
+pub fn literal_or_keys(
+    table: &Table,
+    keys: Option<&(String, String)>,
+) -> (Option<String>, Option<String>) {
+    let (key_kind, key_name) = keys.cloned().unzip();
+
+    (
+        table.get_value("type").or(key_kind),
+        table.get_value("name").or(key_name),
+    )
+}
+
+/// Finds the tables of a file that define prototypes, keyed by the byte
+/// offsets of their opening braces.
+///
+/// Tables passed to `data:extend` and assigned to `data.raw[type][name]` are
+/// always prototypes, the latter with their literal `type` and `name` keys.
+/// With [`Detection::Heuristic`], so are tables with a literal `type` and
+/// `name` that aren't nested in another prototype, like the ones returned by
+/// helper functions.
+pub fn find(ast: &ast::Ast, detection: Detection) -> HashMap<usize, Option<(String, String)>> {
+    let mut finder = Finder {
+        detection,
+        tables: HashMap::new(),
+        open: Vec::new(),
+    };
+
+    finder.visit_ast(ast);
+
+    finder.tables
+}
+
+struct Finder {
+    detection: Detection,
+    tables: HashMap<usize, Option<(String, String)>>,
+    /// Whether every table being visited is or is inside a prototype
+    open: Vec<bool>,
+}
+
+impl Finder {
+    fn insert(&mut self, expression: &Expression, keys: Option<(String, String)>) {
+        if let Expression::TableConstructor(table) = expression {
+            self.tables.insert(brace_offset(table), keys);
+        }
+    }
+}
+
+impl Visitor for Finder {
+    fn visit_function_call(&mut self, node: &FunctionCall) {
+        if !matches!(node.prefix(), Prefix::Name(name) if name.to_string().trim() == "data") {
+            return;
+        }
+
+        let mut suffixes = node.suffixes();
+
+        let (Some(Suffix::Call(Call::MethodCall(call))), None) = (suffixes.next(), suffixes.next())
+        else {
+            return;
+        };
+
+        if call.name().to_string().trim() != "extend" {
+            return;
+        }
+
+        let list = match call.args() {
+            FunctionArgs::Parentheses { arguments, .. } => match arguments.iter().next() {
+                Some(Expression::TableConstructor(list)) => list,
+                _ => return,
+            },
+            FunctionArgs::TableConstructor(list) => list,
+            _ => return,
+        };
+
+        for field in list.fields() {
+            if let ast::Field::NoKey(expression) = field {
+                self.insert(expression, None);
+            }
+        }
+    }
+
+    fn visit_assignment(&mut self, node: &Assignment) {
+        for (variable, expression) in node.variables().iter().zip(node.expressions()) {
+            let Var::Expression(variable) = variable else {
+                continue;
+            };
+
+            if !matches!(variable.prefix(), Prefix::Name(name) if name.to_string().trim() == "data")
+            {
+                continue;
+            }
+
+            let suffixes = variable.suffixes().collect::<Vec<_>>();
+
+            // data.raw[type][name], either index may also use a dot
+            if let [Suffix::Index(Index::Dot { name, .. }), Suffix::Index(kind), Suffix::Index(key)] =
+                suffixes.as_slice()
+            {
+                if name.to_string().trim() == "raw" {
+                    self.insert(expression, index_key(kind).zip(index_key(key)));
+                }
+            }
+        }
+    }
+
+    fn visit_table_constructor(&mut self, node: &TableConstructor) {
+        let enclosed = self.open.iter().any(|prototype| *prototype);
+        let mut prototype = self.tables.contains_key(&brace_offset(node));
+
+        if !prototype && !enclosed && self.detection == Detection::Heuristic {
+            let table = Table::new(node);
+
+            if table.get_value::<String>("type").is_some()
+                && table.get_value::<String>("name").is_some()
+            {
+                self.tables.insert(brace_offset(node), None);
+
+                prototype = true;
+            }
+        }
+
+        self.open.push(prototype || enclosed);
+    }
+
+    fn visit_table_constructor_end(&mut self, _node: &TableConstructor) {
+        self.open.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Source text of every table found as a prototype, in source order.
+    fn prototypes(source: &str, detection: Detection) -> Vec<String> {
+        let ast = full_moon::parse(source).unwrap();
+        let mut offsets = find(&ast, detection).into_keys().collect::<Vec<_>>();
+
+        offsets.sort_unstable();
+
+        offsets
+            .into_iter()
+            .map(|offset| {
+                let end = source[offset..].find('}').unwrap();
+
+                source[offset..=offset + end].to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_tables_passed_to_data_extend() {
+        let source = r#"
+            data:extend{ { type = "item", name = "a" } }
+            data:extend({ { type = "item", name = "b" }, { type = "fluid", name = "c" } })
+        "#;
+
+        for detection in [Detection::Strict, Detection::Heuristic] {
+            assert_eq!(
+                prototypes(source, detection),
+                [
+                    r#"{ type = "item", name = "a" }"#,
+                    r#"{ type = "item", name = "b" }"#,
+                    r#"{ type = "fluid", name = "c" }"#,
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn finds_tables_assigned_to_data_raw() {
+        let source = r#"
+            data.raw.recipe["x"] = { enabled = false }
+            data.raw["item"].y = { stack_size = 50 }
+            data.raw.recipe = { x = {} }
+        "#;
+
+        assert_eq!(
+            prototypes(source, Detection::Strict),
+            ["{ enabled = false }", "{ stack_size = 50 }"]
+        );
+    }
+
+    #[test]
+    fn keeps_the_data_raw_keys() {
+        let source = r#"
+            data.raw.recipe["x"] = { enabled = false }
+            data.raw[kind].y = { enabled = false }
+            data:extend({ { type = "item", name = "z" } })
+        "#;
+        let ast = full_moon::parse(source).unwrap();
+        let mut keys = find(&ast, Detection::Strict)
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        keys.sort_unstable();
+
+        assert_eq!(
+            keys.into_iter().map(|(_, keys)| keys).collect::<Vec<_>>(),
+            [Some(("recipe".to_string(), "x".to_string())), None, None]
+        );
+    }
+
+    #[test]
+    fn skips_results_nested_in_a_prototype() {
+        let source = r#"
+            data:extend({
+                {
+                    type = "recipe",
+                    name = "plate",
+                    results = { { type = "item", name = "plate", amount = 1 } },
+                },
+            })
+        "#;
+
+        for detection in [Detection::Strict, Detection::Heuristic] {
+            let found = prototypes(source, detection);
+
+            assert_eq!(found.len(), 1);
+            assert!(found[0].contains(r#"type = "recipe""#), "{found:?}");
+        }
+    }
+
+    #[test]
+    fn only_heuristic_detection_finds_helper_tables() {
+        let source = r#"
+            local function make_item(name)
+                return { type = "item", name = "helper-item" }
+            end
+
+            data:extend({ make_item("a") })
+        "#;
+
+        assert!(prototypes(source, Detection::Strict).is_empty());
+        assert_eq!(
+            prototypes(source, Detection::Heuristic),
+            [r#"{ type = "item", name = "helper-item" }"#]
+        );
+    }
+
+    #[test]
+    fn indexes_prototypes_across_files() {
+        let mut index = PrototypeIndex::default();
+
+        index.load_source(
+            "a",
+            Path::new("a/data.lua"),
+            r#"data:extend({ { type = "item", name = "plate" }, { type = "recipe", name = "plate" } })"#,
+            Detection::Strict,
+        );
+
+        let mut other = PrototypeIndex::default();
+
+        other.load_source(
+            "b",
+            Path::new("b/data.lua"),
+            r#"data:extend({ { type = "item", name = "plate" } })"#,
+            Detection::Strict,
+        );
+        index.extend(other);
+
+        assert!(index.contains("recipe", "plate"));
+        assert!(!index.contains("fluid", "plate"));
+        assert_eq!(index.kinds_of("plate"), ["item", "recipe"]);
+        // Later definitions replace earlier ones like they do in the game
+        assert_eq!(index.get("item", "plate").unwrap().mod_name, "b");
+        assert_eq!(index.fingerprint(), "item/plate\nrecipe/plate");
+    }
+}
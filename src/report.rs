@@ -0,0 +1,84 @@
+use crate::diagnostics::{Diagnostic, Diagnostics, Severity};
+use serde::Serialize;
+use std::{collections::BTreeMap, path::PathBuf};
+
+/// Machine-readable summary of a run, grouped by mod and file.
+#[derive(Debug, Serialize)]
+pub struct Report<'a> {
+    pub summary: Summary,
+    pub mods: Vec<ModReport<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub fixed: usize,
+    pub skipped: usize,
+    pub warnings: usize,
+    pub errors: usize,
+    pub changed_files: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModReport<'a> {
+    pub name: &'a str,
+    pub files: Vec<FileReport<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileReport<'a> {
+    pub path: &'a PathBuf,
+    pub changed: bool,
+    pub diagnostics: Vec<&'a Diagnostic>,
+}
+
+impl<'a> Report<'a> {
+    pub fn new(diagnostics: &'a Diagnostics) -> Self {
+        let mut mods = BTreeMap::<&str, BTreeMap<&PathBuf, FileReport>>::new();
+
+        for (mod_name, path) in diagnostics.changed() {
+            mods.entry(mod_name)
+                .or_default()
+                .entry(path)
+                .or_insert_with(|| FileReport {
+                    path,
+                    changed: false,
+                    diagnostics: Vec::new(),
+                })
+                .changed = true;
+        }
+
+        for diagnostic in diagnostics.entries() {
+            mods.entry(&diagnostic.mod_name)
+                .or_default()
+                .entry(&diagnostic.file)
+                .or_insert_with(|| FileReport {
+                    path: &diagnostic.file,
+                    changed: false,
+                    diagnostics: Vec::new(),
+                })
+                .diagnostics
+                .push(diagnostic);
+        }
+
+        Self {
+            summary: Summary {
+                fixed: diagnostics.count(Severity::Fixed),
+                skipped: diagnostics.count(Severity::Skipped),
+                warnings: diagnostics.count(Severity::Warning),
+                errors: diagnostics.count(Severity::Error),
+                changed_files: diagnostics.changed().len(),
+            },
+            mods: mods
+                .into_iter()
+                .map(|(name, files)| ModReport {
+                    name,
+                    files: files.into_values().collect(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
@@ -1,31 +1,48 @@
-use super::{FixRule, PrototypeKind};
+use super::{FixRule, OrFail, PrototypeKind, RuleError, RuleOption};
 use crate::Table;
 
 pub const FIX_FLUID_BOXES: FixRule = FixRule {
+    id: "fluid-boxes",
+    description: "Snaps pipe connection positions to the 0.5 tile grid (option `grid`)",
+    changelog: Some("Snapped pipe connection positions to the 0.5 tile grid"),
     enabled: true,
     kind: PrototypeKind::None,
-    filter: |_, _, table| {
-        table.contains_key("name")
+    options: &[RuleOption {
+        name: "grid",
+        check: RuleOption::positive_number,
+    }],
+    filter: |context, table| {
+        context.path.is_prototype()
             && table.contains_key("collision_box")
             && (table.contains_key("fluid_box") || table.contains_key("fluid_boxes"))
     },
-    action: |mod_name, _, _, table| {
-        let name: String = table.get_value("name")?;
+    action: |context, table| {
+        let grid: f32 = context.options.get_or("grid", 0.5);
 
-        let fix_fluid_box = |fluid_box: &mut Table| -> Option<()> {
-            let (mut pipe_connections, pos) =
-                fluid_box.remove_value_pos::<Vec<Table>>("pipe_connections")?;
+        // Whether any connection of the fluid box moved
+        let fix_fluid_box = |fluid_box: &mut Table| -> Result<bool, RuleError> {
+            if !fluid_box.contains_key("pipe_connections") {
+                return Ok(false);
+            }
+
+            let (mut pipe_connections, pos) = fluid_box
+                .remove_value_pos::<Vec<Table>>("pipe_connections")
+                .or_fail("pipe_connections isn't a list of table literals")?;
+            let mut moved = false;
 
             for pipe_connection in &mut pipe_connections {
-                let ([mut x, mut y], pos) =
-                    pipe_connection.remove_value_pos::<[f32; 2]>("position")?;
+                let ([mut x, mut y], pos) = pipe_connection
+                    .remove_value_pos::<[f32; 2]>("position")
+                    .or_fail("a pipe connection position isn't a literal {x, y}")?;
 
-                if x % 0.5 != 0.0 {
-                    x = x - (x % 0.5);
+                if x % grid != 0.0 {
+                    x = x - (x % grid);
+                    moved = true;
                 }
 
-                if y % 0.5 != 0.0 {
-                    y = y - (y % 0.5);
+                if y % grid != 0.0 {
+                    y = y - (y % grid);
+                    moved = true;
                 }
 
                 pipe_connection.insert_at(pos, "position", [x, y]);
@@ -33,27 +50,38 @@ pub const FIX_FLUID_BOXES: FixRule = FixRule {
 
             fluid_box.insert_at(pos, "pipe_connections", pipe_connections);
 
-            Some(())
+            Ok(moved)
         };
 
-        if let Some((mut fluid_box, pos)) = table.remove_value_pos::<Table>("fluid_box") {
-            fix_fluid_box(&mut fluid_box);
+        let message = if table.contains_key("fluid_box") {
+            let (mut fluid_box, pos) = table
+                .remove_value_pos::<Table>("fluid_box")
+                .or_fail("fluid_box isn't a table literal")?;
+            let moved = fix_fluid_box(&mut fluid_box)?;
 
             table.insert_at(pos, "fluid_box", fluid_box);
 
-            println!("[{mod_name}] Fixed fluid box for {name}");
+            moved.then_some("fixed fluid box")
         } else {
-            let (mut fluid_boxes, pos) = table.remove_value_pos::<Vec<Table>>("fluid_boxes")?;
+            let (mut fluid_boxes, pos) = table
+                .remove_value_pos::<Vec<Table>>("fluid_boxes")
+                .or_fail("fluid_boxes isn't a list of table literals")?;
+            let mut moved = false;
 
             for fluid_box in &mut fluid_boxes {
-                fix_fluid_box(fluid_box);
+                moved |= fix_fluid_box(fluid_box)?;
             }
 
             table.insert_at(pos, "fluid_boxes", fluid_boxes);
 
-            println!("[{mod_name}] Fixed fluid boxes for {name}");
-        }
+            moved.then_some("fixed fluid boxes")
+        };
+
+        let message = message
+            .ok_or_else(|| RuleError::skipped("pipe connections are already on the grid"))?;
+
+        context.diagnostics.fixed(message);
 
-        Some(())
+        Ok(())
     },
 };
@@ -1,12 +1,15 @@
-use crate::rules::{FixRule, PrototypeKind};
+use crate::rules::{FixRule, OrFail, PrototypeKind};
 use crate::Table;
-use owo_colors::OwoColorize;
 
 pub const FIX_BEAM_GRAPHICS: FixRule = FixRule {
+    id: "graphics.beam",
+    description: "Moves beam sprites into `graphics_set.beam`",
+    changelog: Some("Moved beam sprites into `graphics_set.beam`"),
     enabled: false,
     kind: PrototypeKind::Single("beam"),
-    filter: |_, _, table| !table.contains_key("graphics_set"),
-    action: |mod_name, prototype_name, _, table| {
+    options: &[],
+    filter: |_, table| !table.contains_key("graphics_set"),
+    action: |context, table| {
         let mut animation = Table::default();
 
         let pos = table
@@ -14,7 +17,8 @@ pub const FIX_BEAM_GRAPHICS: FixRule = FixRule {
             .or_else(|| table.index_of("ending"))
             .or_else(|| table.index_of("head"))
             .or_else(|| table.index_of("tail"))
-            .or_else(|| table.index_of("body"))?;
+            .or_else(|| table.index_of("body"))
+            .or_skip("the beam has no sprites")?;
 
         if let Some(value) = table.remove("start") {
             animation.insert("start", value);
@@ -42,12 +46,10 @@ pub const FIX_BEAM_GRAPHICS: FixRule = FixRule {
             Table::default().with_field("beam", animation),
         );
 
-        println!(
-            "[{}] Fixed graphics for beam prototype called {}",
-            mod_name.bright_red(),
-            prototype_name.bright_blue(),
-        );
+        context
+            .diagnostics
+            .fixed("moved sprites into graphics_set.beam");
 
-        Some(())
+        Ok(())
     },
 };
@@ -1,16 +1,22 @@
-use crate::rules::{FixRule, PrototypeKind};
+use crate::rules::{FixRule, OrFail, PrototypeKind};
 use crate::{MaybeInto, Table, Value};
 use full_moon::ast;
-use owo_colors::OwoColorize;
 
 pub const FIX_HIGH_RES_GRAPHICS: FixRule = FixRule {
+    id: "graphics.hr-version",
+    description: "Replaces sprites with their `hr_version`, 2.0 has no low resolution graphics",
+    changelog: Some("Replaced sprites with their `hr_version`"),
     enabled: false,
     kind: PrototypeKind::None,
-    filter: |_, _, table| table.contains_key("hr_version"),
-    action: |mod_name, _, _, table| {
+    options: &[],
+    // Sprites are never prototypes themselves, but may be defined outside of one
+    filter: |context, table| !context.path.is_prototype() && table.contains_key("hr_version"),
+    action: |context, table| {
         // let mut graphics_set = Table::default();
 
-        let high_res_version = table.remove("hr_version")?;
+        let high_res_version = table
+            .remove("hr_version")
+            .or_skip("the sprite has no hr_version")?;
 
         let high_res_table: Table = if let ast::Expression::BinaryOperator {
             lhs,
@@ -24,21 +30,23 @@ pub const FIX_HIGH_RES_GRAPHICS: FixRule = FixRule {
                 ..
             } = *lhs
             {
-                MaybeInto::try_into(Value::from_raw(*rhs)?)
+                Value::from_raw(*rhs)
             } else {
-                MaybeInto::try_into(Value::from_raw(*lhs)?)
+                Value::from_raw(*lhs)
             }
         } else {
-            MaybeInto::try_into(Value::from_raw(high_res_version)?)
-        }?;
+            Value::from_raw(high_res_version)
+        }
+        .and_then(MaybeInto::try_into)
+        .or_fail("hr_version isn't a table literal")?;
 
-        let filename: String = high_res_table.get_value("filename")?;
+        let filename: String = high_res_table
+            .get_value("filename")
+            .or_fail("hr_version has no literal filename")?;
 
-        println!(
-            "[{}] Fixed graphics for {}",
-            mod_name.bright_green(),
-            filename.bright_blue()
-        );
+        context
+            .diagnostics
+            .fixed(format!("replaced sprite with {filename}"));
         // let filename = filename.replace("/hr-", "/");
 
         // let pos = high_res_table.index_of("filename")?;
@@ -61,6 +69,6 @@ pub const FIX_HIGH_RES_GRAPHICS: FixRule = FixRule {
         table.clear();
         table.extend(high_res_table);
 
-        Some(())
+        Ok(())
     },
 };
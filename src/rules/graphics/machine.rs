@@ -1,8 +1,10 @@
-use crate::rules::{FixRule, PrototypeKind};
+use crate::rules::{FixRule, OrFail, PrototypeKind};
 use crate::Table;
-use owo_colors::OwoColorize;
 
 pub const FIX_MACHINE_GRAPHICS: FixRule = FixRule {
+    id: "graphics.machine",
+    description: "Moves crafting machine animations into `graphics_set`",
+    changelog: Some("Moved crafting machine animations into `graphics_set`"),
     enabled: false,
     kind: PrototypeKind::Verify(|kind| {
         matches!(
@@ -10,19 +12,21 @@ pub const FIX_MACHINE_GRAPHICS: FixRule = FixRule {
             "assembling-machine" | "furnace" | "mining-drill" | "rocket-silo"
         )
     }),
-    filter: |_, _, table| {
+    options: &[],
+    filter: |_, table| {
         !table.contains_key("graphics_set")
             && (table.contains_key("animation")
                 || table.contains_key("idle_animation")
                 || table.contains_key("working_visualisations"))
     },
-    action: |mod_name, prototype_name, _, table| {
+    action: |context, table| {
         let mut graphics_set = Table::default();
 
         let pos = table
             .index_of("animation")
             .or_else(|| table.index_of("idle_animation"))
-            .or_else(|| table.index_of("working_visualisations"))?;
+            .or_else(|| table.index_of("working_visualisations"))
+            .or_skip("the machine has no animations")?;
 
         if let Some(value) = table.remove("animation") {
             graphics_set.insert("animation", value);
@@ -38,12 +42,10 @@ pub const FIX_MACHINE_GRAPHICS: FixRule = FixRule {
 
         table.insert_at(pos, "graphics_set", graphics_set);
 
-        println!(
-            "[{}] Fixed graphics for assembling machine prototype called {}",
-            mod_name.bright_red(),
-            prototype_name.bright_blue(),
-        );
+        context
+            .diagnostics
+            .fixed("moved animations into graphics_set");
 
-        Some(())
+        Ok(())
     },
 };
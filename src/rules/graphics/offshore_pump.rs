@@ -2,10 +2,14 @@ use crate::rules::{FixRule, PrototypeKind};
 use crate::Table;
 
 pub const FIX_OFFSHORE_PUMP_GRAPHICS: FixRule = FixRule {
+    id: "graphics.offshore-pump",
+    description: "Moves offshore pump `picture` into `graphics_set.base_pictures`",
+    changelog: Some("Moved offshore pump `picture` into `graphics_set.base_pictures`"),
     enabled: false,
     kind: PrototypeKind::Single("offshore-pump"),
-    filter: |_, _, table| !table.contains_key("graphics_set"),
-    action: |_, _, _, table| {
+    options: &[],
+    filter: |_, table| !table.contains_key("graphics_set"),
+    action: |context, table| {
         let mut graphics_set = Table::default();
 
         let pos = table.index_of("picture");
@@ -20,6 +24,10 @@ pub const FIX_OFFSHORE_PUMP_GRAPHICS: FixRule = FixRule {
             table.insert("graphics_set", graphics_set);
         }
 
-        Some(())
+        context
+            .diagnostics
+            .fixed("moved picture into graphics_set.base_pictures");
+
+        Ok(())
     },
 };
@@ -1,8 +1,10 @@
-use crate::rules::{FixRule, PrototypeKind};
+use crate::rules::{FixRule, OrFail, PrototypeKind};
 use crate::Table;
-use owo_colors::OwoColorize;
 
 pub const FIX_TURRET_GRAPHICS: FixRule = FixRule {
+    id: "graphics.turret",
+    description: "Moves turret `base_picture` into `graphics_set.base_visualisation`",
+    changelog: Some("Moved turret `base_picture` into `graphics_set.base_visualisation`"),
     enabled: false,
     kind: PrototypeKind::Verify(|kind| {
         matches!(
@@ -10,25 +12,31 @@ pub const FIX_TURRET_GRAPHICS: FixRule = FixRule {
             "turret" | "electric-turret" | "ammo-turret" | "fluid-turret"
         )
     }),
-    filter: |_, _, table| !table.contains_key("graphics_set") && table.contains_key("base_picture"),
-    action: |mod_name, prototype_name, _, table| {
+    options: &[],
+    filter: |_, table| !table.contains_key("graphics_set") && table.contains_key("base_picture"),
+    action: |context, table| {
         let mut graphics_set = Table::default();
 
-        let pos = table.index_of("base_picture")?;
+        let pos = table
+            .index_of("base_picture")
+            .or_skip("the turret has no base_picture")?;
 
         graphics_set.insert(
             "base_visualisation",
-            Table::default().with_field("animation", table.remove("base_picture")?),
+            Table::default().with_field(
+                "animation",
+                table
+                    .remove("base_picture")
+                    .or_skip("the turret has no base_picture")?,
+            ),
         );
 
         table.insert_at(pos, "graphics_set", graphics_set);
 
-        println!(
-            "[{}] Fixed graphics for turret prototype called {}",
-            mod_name.bright_red(),
-            prototype_name.bright_blue(),
-        );
+        context
+            .diagnostics
+            .fixed("moved base_picture into graphics_set.base_visualisation");
 
-        Some(())
+        Ok(())
     },
 };
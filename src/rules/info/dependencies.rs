@@ -0,0 +1,119 @@
+use super::{major_version, Dependency, InfoRule};
+use crate::rules::RuleOption;
+use serde_json::Value;
+use std::collections::HashMap;
+
+pub const FIX_BASE_DEPENDENCY: InfoRule = InfoRule {
+    id: "info.base-dependency",
+    description: "Updates `base` dependencies on 1.1 to the 2.0 version",
+    changelog: Some("Updated the `base` dependency to 2.0"),
+    enabled: false,
+    options: &[RuleOption {
+        name: "version",
+        check: RuleOption::typed::<String>,
+    }],
+    requires: &[],
+    action: |diagnostics, options, info| {
+        let version = options.get_or("version", "2.0.0".to_string());
+
+        let Some(dependencies) = info.get_mut("dependencies").and_then(Value::as_array_mut) else {
+            return Ok(());
+        };
+
+        for value in dependencies {
+            let Some(dependency) = value.as_str().map(Dependency::parse) else {
+                continue;
+            };
+
+            if dependency.name != "base" {
+                continue;
+            }
+
+            let Some((operator, current)) = &dependency.version else {
+                continue;
+            };
+
+            if major_version(current).is_none_or(|major| major >= 2) {
+                continue;
+            }
+
+            if operator.starts_with('<') {
+                diagnostics.warning(format!(
+                    "`{dependency}` keeps the mod from loading on 2.0, fix it by hand"
+                ));
+
+                continue;
+            }
+
+            let updated = Dependency {
+                version: Some((operator.clone(), version.clone())),
+                ..dependency.clone()
+            };
+
+            diagnostics.fixed(format!("`{dependency}` updated to `{updated}`"));
+
+            *value = Value::from(updated.to_string());
+        }
+
+        Ok(())
+    },
+};
+
+pub const FLAG_DEPENDENCIES: InfoRule = InfoRule {
+    id: "info.dependencies",
+    description: "Flags dependencies on mods that were removed or renamed for 2.0",
+    changelog: None,
+    enabled: false,
+    options: &[
+        RuleOption {
+            name: "removed",
+            check: RuleOption::typed::<Vec<String>>,
+        },
+        RuleOption {
+            name: "renamed",
+            check: RuleOption::typed::<HashMap<String, String>>,
+        },
+    ],
+    requires: &[],
+    action: |diagnostics, options, info| {
+        let removed = options.get_or("removed", Vec::<String>::new());
+        let renamed = options.get_or("renamed", HashMap::<String, String>::new());
+
+        if removed.is_empty() && renamed.is_empty() {
+            diagnostics.warning(
+                "no mods are configured as `removed` or `renamed`, so no dependency is flagged",
+            );
+
+            return Ok(());
+        }
+
+        let Some(dependencies) = info.get("dependencies").and_then(Value::as_array) else {
+            return Ok(());
+        };
+
+        for value in dependencies {
+            let Some(dependency) = value.as_str().map(Dependency::parse) else {
+                continue;
+            };
+
+            // Being incompatible with a mod that's gone does no harm
+            if !dependency.is_compatible() {
+                continue;
+            }
+
+            if removed.contains(&dependency.name) {
+                diagnostics.warning(format!(
+                    "depends on {}, which was removed in 2.0",
+                    dependency.name
+                ));
+            } else if let Some(name) = renamed.get(&dependency.name) {
+                diagnostics.warning(format!(
+                    "depends on {}, which was renamed to {name} in 2.0",
+                    dependency.name
+                ));
+            }
+        }
+
+        Ok(())
+    },
+};
@@ -0,0 +1,34 @@
+use super::InfoRule;
+use crate::rules::{OrFail, RuleError};
+use serde_json::Value;
+
+pub const FIX_FACTORIO_VERSION: InfoRule = InfoRule {
+    id: "info.factorio-version",
+    description: "Bumps `factorio_version` from 1.1 to 2.0",
+    changelog: Some("Updated `factorio_version` to 2.0"),
+    enabled: false,
+    options: &[],
+    requires: &[],
+    action: |diagnostics, _options, info| {
+        let version = info
+            .get("factorio_version")
+            .and_then(Value::as_str)
+            .or_fail("info.json has no factorio_version")?;
+
+        match version {
+            "2.0" => {}
+            "1.1" => {
+                info.insert("factorio_version".to_string(), Value::from("2.0"));
+
+                diagnostics.fixed("factorio_version bumped from 1.1 to 2.0");
+            }
+            _ => {
+                return Err(RuleError::failed(format!(
+                    "factorio_version is {version}, only mods for 1.1 can be migrated"
+                )))
+            }
+        }
+
+        Ok(())
+    },
+};
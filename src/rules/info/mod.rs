@@ -0,0 +1,308 @@
+use super::{RuleError, RuleOption, RuleOptions, RuleResult, RuleSet};
+use crate::diagnostics::Diagnostics;
+use dependencies::{FIX_BASE_DEPENDENCY, FLAG_DEPENDENCIES};
+use factorio_version::FIX_FACTORIO_VERSION;
+use serde::Serialize;
+use serde_json::{ser::PrettyFormatter, Map, Serializer, Value};
+use std::fmt;
+use version::BUMP_VERSION;
+
+pub mod dependencies;
+pub mod factorio_version;
+mod patch;
+pub mod version;
+
+pub const FILE_NAME: &str = "info.json";
+
+/// A rule applied to the `info.json` of a mod instead of its Lua tables.
+#[derive(Debug)]
+pub struct InfoRule {
+    /// Stable identifier used to select the rule, groups are separated by dots
+    pub id: &'static str,
+    pub description: &'static str,
+    /// Line added to the changelog of mods the rule fixed something in
+    pub changelog: Option<&'static str>,
+    /// Whether the rule runs when no selection mentions it
+    pub enabled: bool,
+    pub options: &'static [RuleOption],
+    /// Rules that have to be enabled for the same mods, the rule is skipped
+    /// without them
+    pub requires: &'static [&'static str],
+    pub action: fn(&mut Diagnostics, &RuleOptions, &mut Map<String, Value>) -> RuleResult,
+}
+
+/// Every known info.json rule, in the order they are applied.
+pub fn all() -> Vec<InfoRule> {
+    vec![
+        BUMP_VERSION,
+        FIX_FACTORIO_VERSION,
+        FIX_BASE_DEPENDENCY,
+        FLAG_DEPENDENCIES,
+    ]
+}
+
+/// Applies the info.json rules of `rule_set` to `source`, returns the rewritten
+/// file if anything changed.
+///
+/// Keys keep their order and the file keeps its formatting, it is only
+/// re-indented as a whole when keys were added or removed.
+pub fn apply(
+    diagnostics: &mut Diagnostics,
+    rule_set: &RuleSet,
+    source: &str,
+) -> serde_json::Result<Option<String>> {
+    let Value::Object(mut info) = serde_json::from_str::<Value>(source)? else {
+        diagnostics.error("info.json doesn't contain an object");
+
+        return Ok(None);
+    };
+
+    let original = info.clone();
+
+    for rule in &rule_set.info {
+        diagnostics.set_rule(Some(rule.id));
+
+        if let Some(required) = rule
+            .requires
+            .iter()
+            .find(|id| !rule_set.info.iter().any(|rule| rule.id == **id))
+        {
+            RuleError::skipped(format!("{} only runs together with {required}", rule.id))
+                .report(diagnostics);
+
+            continue;
+        }
+
+        let before = info.clone();
+
+        if let Err(error) = (rule.action)(diagnostics, &rule_set.options[rule.id], &mut info) {
+            error.report(diagnostics);
+
+            info = before;
+        }
+    }
+
+    diagnostics.set_rule(None);
+
+    if info == original {
+        return Ok(None);
+    }
+
+    // Only the changed values are rewritten when possible, so the file keeps
+    // its layout
+    if let Some(output) = patch::patch(
+        source,
+        &Value::Object(original),
+        &Value::Object(info.clone()),
+    ) {
+        return Ok(Some(output));
+    }
+
+    let indent = detect_indent(source);
+    let mut output = Vec::new();
+    let mut serializer =
+        Serializer::with_formatter(&mut output, PrettyFormatter::with_indent(indent.as_bytes()));
+
+    info.serialize(&mut serializer)?;
+
+    let mut output = String::from_utf8(output).unwrap_or_default();
+
+    if source.ends_with('\n') {
+        output.push('\n');
+    }
+
+    Ok(Some(output))
+}
+
+/// Indentation of the first indented line, two spaces if there is none.
+fn detect_indent(source: &str) -> String {
+    source
+        .lines()
+        .map(|line| &line[..line.len() - line.trim_start().len()])
+        .find(|indent| !indent.is_empty())
+        .unwrap_or("  ")
+        .to_string()
+}
+
+/// A single entry of `dependencies`, e.g. `? some-mod >= 1.2.0`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dependency {
+    /// `!`, `?`, `(?)` or `~`, empty for hard requirements
+    pub prefix: String,
+    pub name: String,
+    /// Comparison operator and version, if the dependency has one
+    pub version: Option<(String, String)>,
+}
+
+impl Dependency {
+    const PREFIXES: [&'static str; 4] = ["(?)", "!", "?", "~"];
+    const OPERATORS: [&'static str; 5] = [">=", "<=", "=", ">", "<"];
+
+    pub fn parse(value: &str) -> Self {
+        let value = value.trim();
+        let (prefix, rest) = Self::PREFIXES
+            .iter()
+            .find_map(|prefix| value.strip_prefix(prefix).map(|rest| (*prefix, rest)))
+            .unwrap_or(("", value));
+
+        // Operators are checked longest first, so `>=` isn't read as `>`
+        let version = Self::OPERATORS.iter().find_map(|operator| {
+            rest.find(operator).map(|index| {
+                (
+                    index,
+                    operator.to_string(),
+                    rest[index + operator.len()..].trim().to_string(),
+                )
+            })
+        });
+
+        match version {
+            Some((index, operator, version)) => Self {
+                prefix: prefix.to_string(),
+                name: rest[..index].trim().to_string(),
+                version: Some((operator, version)),
+            },
+            None => Self {
+                prefix: prefix.to_string(),
+                name: rest.trim().to_string(),
+                version: None,
+            },
+        }
+    }
+
+    /// Whether the mod is loaded with this one, `false` for incompatibilities.
+    pub fn is_compatible(&self) -> bool {
+        self.prefix != "!"
+    }
+}
+
+impl fmt::Display for Dependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.prefix.is_empty() {
+            write!(f, "{} ", self.prefix)?;
+        }
+
+        f.write_str(&self.name)?;
+
+        if let Some((operator, version)) = &self.version {
+            write!(f, " {operator} {version}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Major version of a `major.minor.patch` string.
+pub fn major_version(version: &str) -> Option<u32> {
+    version.split('.').next()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{diagnostics::Severity, rules::RuleSelection};
+    use std::collections::HashMap;
+
+    const INFO: &str = r#"{"name": "mymod", "version": "1.0.0", "factorio_version": "1.1", "dependencies": ["base >= 1.1.0"]}"#;
+
+    fn rule_set(enable: &[&str]) -> RuleSet {
+        let mut selection = RuleSelection::new(Vec::new(), vec!["*".to_string()]);
+
+        selection.merge(RuleSelection::new(
+            enable.iter().map(ToString::to_string).collect(),
+            Vec::new(),
+        ));
+
+        RuleSet::new(&selection, HashMap::new())
+    }
+
+    #[test]
+    fn version_is_only_bumped_with_the_factorio_version() {
+        let mut diagnostics = Diagnostics::default();
+
+        let output = apply(&mut diagnostics, &rule_set(&["info.version"]), INFO).unwrap();
+
+        assert_eq!(output, None);
+        assert_eq!(diagnostics.count(Severity::Skipped), 1);
+
+        let rule_set = rule_set(&["info.version", "info.factorio-version"]);
+        let output = apply(&mut diagnostics, &rule_set, INFO).unwrap().unwrap();
+
+        assert!(output.contains(r#""version": "1.1.0""#), "{output}");
+        assert!(output.contains(r#""factorio_version": "2.0""#), "{output}");
+
+        // Running again doesn't bump twice
+        assert_eq!(apply(&mut diagnostics, &rule_set, &output).unwrap(), None);
+    }
+
+    #[test]
+    fn unconfigured_dependency_flags_warn() {
+        let mut diagnostics = Diagnostics::default();
+
+        apply(&mut diagnostics, &rule_set(&["info.dependencies"]), INFO).unwrap();
+
+        assert_eq!(diagnostics.count(Severity::Warning), 1);
+        assert!(diagnostics.entries()[0].message.contains("removed"));
+    }
+
+    #[test]
+    fn parses_dependencies() {
+        assert_eq!(
+            Dependency::parse("? some-mod >= 1.2.0"),
+            Dependency {
+                prefix: "?".to_string(),
+                name: "some-mod".to_string(),
+                version: Some((">=".to_string(), "1.2.0".to_string())),
+            }
+        );
+        assert_eq!(
+            Dependency::parse("(?)other"),
+            Dependency {
+                prefix: "(?)".to_string(),
+                name: "other".to_string(),
+                version: None,
+            }
+        );
+        assert_eq!(
+            Dependency::parse(" base>1.1 "),
+            Dependency {
+                prefix: String::new(),
+                name: "base".to_string(),
+                version: Some((">".to_string(), "1.1".to_string())),
+            }
+        );
+    }
+
+    #[test]
+    fn displays_dependencies_normalized() {
+        for (value, expected) in [
+            ("base >= 1.1.0", "base >= 1.1.0"),
+            ("!gone-mod", "! gone-mod"),
+            ("~ mod = 2.0.0", "~ mod = 2.0.0"),
+            ("(?)mod<3", "(?) mod < 3"),
+        ] {
+            assert_eq!(Dependency::parse(value).to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn incompatibilities_are_not_compatible() {
+        assert!(!Dependency::parse("! gone-mod").is_compatible());
+        assert!(Dependency::parse("? optional").is_compatible());
+        assert!(Dependency::parse("base").is_compatible());
+    }
+
+    #[test]
+    fn reads_major_versions() {
+        assert_eq!(major_version("1.1.110"), Some(1));
+        assert_eq!(major_version("2.0"), Some(2));
+        assert_eq!(major_version("latest"), None);
+    }
+
+    #[test]
+    fn detects_json_indentation() {
+        assert_eq!(detect_indent("{\n\t\"name\": \"a\"\n}"), "\t");
+        assert_eq!(detect_indent("{\n    \"name\": \"a\"\n}"), "    ");
+        assert_eq!(detect_indent(r#"{"name":"a"}"#), "  ");
+    }
+}
@@ -0,0 +1,274 @@
+use serde_json::Value;
+use std::ops::Range;
+
+/// Where a JSON value and its elements are in the source text.
+#[derive(Debug)]
+enum Node {
+    Scalar(Range<usize>),
+    Array(Range<usize>, Vec<Self>),
+    Object(Range<usize>, Vec<(String, Self)>),
+}
+
+impl Node {
+    fn range(&self) -> Range<usize> {
+        match self {
+            Self::Scalar(range) | Self::Array(range, _) | Self::Object(range, _) => range.clone(),
+        }
+    }
+}
+
+/// Rewrites the values of `source` that differ between `old` and `new`,
+/// leaving every other byte as it was.
+///
+/// Returns `None` if the change can't be expressed as value replacements, e.g.
+/// when keys were added or removed.
+pub fn patch(source: &str, old: &Value, new: &Value) -> Option<String> {
+    let mut scanner = Scanner { source, pos: 0 };
+    let node = scanner.value()?;
+    let mut edits = Vec::new();
+
+    collect_edits(source, &node, old, new, &mut edits)?;
+
+    edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+
+    let mut output = source.to_string();
+
+    for (range, text) in edits {
+        output.replace_range(range, &text);
+    }
+
+    Some(output)
+}
+
+fn collect_edits(
+    source: &str,
+    node: &Node,
+    old: &Value,
+    new: &Value,
+    edits: &mut Vec<(Range<usize>, String)>,
+) -> Option<()> {
+    if old == new {
+        return Some(());
+    }
+
+    match (node, old, new) {
+        (Node::Array(_, nodes), Value::Array(old), Value::Array(new))
+            if nodes.len() == old.len() && old.len() == new.len() =>
+        {
+            for ((node, old), new) in nodes.iter().zip(old).zip(new) {
+                collect_edits(source, node, old, new, edits)?;
+            }
+        }
+        (Node::Object(_, nodes), Value::Object(old), Value::Object(new)) => {
+            if nodes.len() != old.len() || !old.keys().eq(new.keys()) {
+                return None;
+            }
+
+            for (key, node) in nodes {
+                collect_edits(source, node, old.get(key)?, new.get(key)?, edits)?;
+            }
+        }
+        // Objects nested in a replaced value and arrays spanning several lines
+        // would lose their layout
+        (_, _, Value::Object(_)) => return None,
+        (Node::Array(range, _), _, _) if source[range.clone()].contains('\n') => return None,
+        _ => edits.push((node.range(), serde_json::to_string(new).ok()?)),
+    }
+
+    Some(())
+}
+
+/// Finds the values of a document that already parsed as JSON.
+struct Scanner<'a> {
+    source: &'a str,
+    pos: usize,
+}
+
+impl Scanner<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.source.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|byte| byte.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        self.skip_whitespace();
+
+        if self.peek()? != byte {
+            return None;
+        }
+
+        self.pos += 1;
+
+        Some(())
+    }
+
+    fn value(&mut self) -> Option<Node> {
+        self.skip_whitespace();
+
+        match self.peek()? {
+            b'{' => self.object(),
+            b'[' => self.array(),
+            b'"' => self.string().map(Node::Scalar),
+            _ => {
+                let start = self.pos;
+
+                while self.peek().is_some_and(|byte| {
+                    !matches!(byte, b',' | b']' | b'}') && !byte.is_ascii_whitespace()
+                }) {
+                    self.pos += 1;
+                }
+
+                Some(Node::Scalar(start..self.pos))
+            }
+        }
+    }
+
+    fn string(&mut self) -> Option<Range<usize>> {
+        let start = self.pos;
+
+        self.expect(b'"')?;
+
+        loop {
+            match self.peek()? {
+                b'\\' => self.pos += 2,
+                b'"' => {
+                    self.pos += 1;
+
+                    return Some(start..self.pos);
+                }
+                _ => self.pos += 1,
+            }
+        }
+    }
+
+    fn array(&mut self) -> Option<Node> {
+        let start = self.pos;
+        let mut nodes = Vec::new();
+
+        self.expect(b'[')?;
+        self.skip_whitespace();
+
+        if self.peek()? != b']' {
+            loop {
+                nodes.push(self.value()?);
+                self.skip_whitespace();
+
+                if self.peek()? != b',' {
+                    break;
+                }
+
+                self.pos += 1;
+            }
+        }
+
+        self.expect(b']')?;
+
+        Some(Node::Array(start..self.pos, nodes))
+    }
+
+    fn object(&mut self) -> Option<Node> {
+        let start = self.pos;
+        let mut nodes = Vec::new();
+
+        self.expect(b'{')?;
+        self.skip_whitespace();
+
+        if self.peek()? != b'}' {
+            loop {
+                self.skip_whitespace();
+
+                let key = self.string()?;
+                let key = serde_json::from_str::<String>(&self.source[key]).ok()?;
+
+                self.expect(b':')?;
+                nodes.push((key, self.value()?));
+                self.skip_whitespace();
+
+                if self.peek()? != b',' {
+                    break;
+                }
+
+                self.pos += 1;
+            }
+        }
+
+        self.expect(b'}')?;
+
+        Some(Node::Object(start..self.pos, nodes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn patched(source: &str, change: impl FnOnce(&mut Value)) -> Option<String> {
+        let old = serde_json::from_str::<Value>(source).unwrap();
+        let mut new = old.clone();
+
+        change(&mut new);
+
+        patch(source, &old, &new)
+    }
+
+    #[test]
+    fn keeps_a_single_line_document_on_one_line() {
+        let output = patched(
+            r#"{"name":"mymod","version":"1.0.0","factorio_version":"1.1"}"#,
+            |info| info["factorio_version"] = json!("2.0"),
+        );
+
+        assert_eq!(
+            output.as_deref(),
+            Some(r#"{"name":"mymod","version":"1.0.0","factorio_version":"2.0"}"#)
+        );
+    }
+
+    #[test]
+    fn only_replaces_changed_array_elements() {
+        let source = "{\n\t\"dependencies\" : [ \"base >= 1.1.0\",\n\t\t\"? other\" ]\n}\n";
+        let output = patched(source, |info| {
+            info["dependencies"][0] = json!("base >= 2.0.0");
+        });
+
+        assert_eq!(
+            output.as_deref(),
+            Some("{\n\t\"dependencies\" : [ \"base >= 2.0.0\",\n\t\t\"? other\" ]\n}\n")
+        );
+    }
+
+    #[test]
+    fn handles_escapes_and_nested_values() {
+        let source = r#"{"title": "a \"quoted\" } title", "x": {"y": [1, 2.5, null]}, "v": "1"}"#;
+        let output = patched(source, |info| info["v"] = json!("2"));
+
+        assert_eq!(
+            output.as_deref(),
+            Some(r#"{"title": "a \"quoted\" } title", "x": {"y": [1, 2.5, null]}, "v": "2"}"#)
+        );
+    }
+
+    #[test]
+    fn replaces_single_line_arrays_that_changed_length() {
+        let output = patched(r#"{"dependencies": ["base", "? old"]}"#, |info| {
+            info["dependencies"] = json!(["base"]);
+        });
+
+        assert_eq!(output.as_deref(), Some(r#"{"dependencies": ["base"]}"#));
+    }
+
+    #[test]
+    fn gives_up_when_keys_change() {
+        let output = patched(r#"{"name": "mymod"}"#, |info| {
+            info["version"] = json!("1.0.0");
+        });
+
+        assert_eq!(output, None);
+    }
+}
@@ -0,0 +1,66 @@
+use super::InfoRule;
+use crate::rules::{OrFail, RuleError, RuleOption};
+use serde_json::Value;
+
+pub const BUMP_VERSION: InfoRule = InfoRule {
+    id: "info.version",
+    description: "Bumps the mod `version` when `info.factorio-version` migrates it to 2.0",
+    changelog: None,
+    enabled: false,
+    options: &[RuleOption {
+        name: "bump",
+        check: |value| match value.as_str() {
+            Some("major" | "minor" | "patch") => Ok(()),
+            _ => Err(format!(
+                "expected \"major\", \"minor\" or \"patch\", found {value}"
+            )),
+        },
+    }],
+    // The version is only bumped while factorio_version still says 1.1, which
+    // only stays true between runs if nothing migrates it
+    requires: &["info.factorio-version"],
+    action: |diagnostics, options, info| {
+        // Only mods that haven't been migrated yet, so running twice doesn't bump twice
+        if info.get("factorio_version").and_then(Value::as_str) != Some("1.1") {
+            return Ok(());
+        }
+
+        let version = info
+            .get("version")
+            .and_then(Value::as_str)
+            .or_fail("info.json has no version")?
+            .to_string();
+        let mut parts = version
+            .split('.')
+            .map(|part| part.trim().parse::<u32>().ok())
+            .collect::<Option<Vec<_>>>()
+            .filter(|parts| parts.len() == 3);
+
+        let Some([major, minor, patch]) = parts.as_deref_mut() else {
+            return Err(RuleError::failed(format!(
+                "version {version} isn't in the major.minor.patch format"
+            )));
+        };
+
+        let bump = options.get_or("bump", "minor".to_string());
+
+        match bump.as_str() {
+            "major" => (*major, *minor, *patch) = (*major + 1, 0, 0),
+            "minor" => (*minor, *patch) = (*minor + 1, 0),
+            "patch" => *patch += 1,
+            _ => {
+                return Err(RuleError::failed(format!(
+                    "unknown bump `{bump}`, expected major, minor or patch"
+                )))
+            }
+        }
+
+        let bumped = format!("{major}.{minor}.{patch}");
+
+        info.insert("version".to_string(), Value::from(bumped.as_str()));
+
+        diagnostics.fixed(format!("version bumped from {version} to {bumped}"));
+
+        Ok(())
+    },
+};
@@ -1,10 +1,34 @@
-use crate::{locales::Locales, Table};
+use crate::{
+    cli::{Detection, Formatting},
+    diagnostics::{Diagnostics, Severity},
+    locales::Locales,
+    prototypes::PrototypeIndex,
+    Mode, Table,
+};
+use fluid_boxes::FIX_FLUID_BOXES;
+use full_moon::tokenizer::Position;
+use graphics::{
+    beam::FIX_BEAM_GRAPHICS, hr_version::FIX_HIGH_RES_GRAPHICS, machine::FIX_MACHINE_GRAPHICS,
+    offshore_pump::FIX_OFFSHORE_PUMP_GRAPHICS, turret::FIX_TURRET_GRAPHICS,
+};
+use info::InfoRule;
+use path::TablePath;
+use recipe::FIX_RECIPE;
+use serde::de::DeserializeOwned;
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt::{self, Write},
+    path::Path,
+};
 
 pub mod fluid_boxes;
 pub mod graphics;
+pub mod info;
+pub mod path;
 pub mod recipe;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum PrototypeKind {
     Single(&'static str),
     Verify(fn(&str) -> bool),
@@ -26,11 +50,598 @@ impl PrototypeKind {
     }
 }
 
+/// Everything a rule knows about the table it is applied to.
+#[derive(Debug)]
+pub struct RuleContext<'a> {
+    pub mod_name: &'a str,
+    pub file: &'a Path,
+    /// Prototype `type`, `None` for tables without one
+    pub kind: Option<&'a str>,
+    /// Prototype `name`, `None` for tables without one
+    pub name: Option<&'a str>,
+    /// Where the table sits in the enclosing prototype
+    pub path: &'a TablePath,
+    pub locales: &'a Locales,
+    /// Every prototype of the run, only filled if an enabled rule asks for it
+    /// with [`Rule::uses_prototypes`]
+    pub prototypes: &'a PrototypeIndex,
+    /// Options of the rule being applied
+    pub options: &'a RuleOptions,
+    /// Settings of the run the rule is applied in
+    pub settings: RunSettings,
+    pub diagnostics: &'a mut Diagnostics,
+}
+
+/// Settings of a run that every rule may look at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunSettings {
+    /// What happens to the files the rules change
+    pub mode: Mode,
+    pub formatting: Formatting,
+    pub detection: Detection,
+}
+
+/// Why a rule left a table alone.
+///
+/// Any change the rule made to the table before failing is discarded, the
+/// remaining rules still run.
 #[derive(Debug)]
+pub struct RuleError {
+    /// [`Severity::Skipped`] if the rule chose not to fix the table,
+    /// [`Severity::Warning`] if it couldn't
+    pub severity: Severity,
+    pub position: Option<Position>,
+    pub reason: String,
+}
+
+impl RuleError {
+    pub fn skipped<T: Into<String>>(reason: T) -> Self {
+        Self {
+            severity: Severity::Skipped,
+            position: None,
+            reason: reason.into(),
+        }
+    }
+
+    pub fn failed<T: Into<String>>(reason: T) -> Self {
+        Self {
+            severity: Severity::Warning,
+            position: None,
+            reason: reason.into(),
+        }
+    }
+
+    /// Points the diagnostic at a specific field instead of the whole table.
+    #[must_use]
+    pub const fn at(mut self, position: Option<Position>) -> Self {
+        self.position = position;
+        self
+    }
+
+    pub fn report(self, diagnostics: &mut Diagnostics) {
+        diagnostics.push_at(self.severity, self.position, self.reason);
+    }
+}
+
+pub type RuleResult = Result<(), RuleError>;
+
+/// Turns a missing value into a [`RuleError`], so rules can use `?` and still
+/// say why they gave up.
+pub trait OrFail<T> {
+    fn or_fail<R: Into<String>>(self, reason: R) -> Result<T, RuleError>;
+
+    fn or_skip<R: Into<String>>(self, reason: R) -> Result<T, RuleError>;
+}
+
+impl<T> OrFail<T> for Option<T> {
+    fn or_fail<R: Into<String>>(self, reason: R) -> Result<T, RuleError> {
+        self.ok_or_else(|| RuleError::failed(reason))
+    }
+
+    fn or_skip<R: Into<String>>(self, reason: R) -> Result<T, RuleError> {
+        self.ok_or_else(|| RuleError::skipped(reason))
+    }
+}
+
+/// A rule applied to Lua tables.
+///
+/// Rules are created once per run and shared by every mod. Each file is fixed
+/// by a [`Rule::fork`] that is merged back in path order, so rules may collect
+/// state over the whole run, e.g. every renamed prototype, and report it in
+/// [`Rule::finish`].
+pub trait Rule: Any + Send + Sync + fmt::Debug {
+    /// Stable identifier used to select the rule, groups are separated by dots
+    fn id(&self) -> &'static str;
+
+    fn description(&self) -> &'static str;
+
+    /// Line added to the changelog of mods the rule fixed something in, in
+    /// the past tense, `None` leaves the rule out of it.
+    fn changelog(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Whether the rule runs when no selection mentions it
+    fn enabled(&self) -> bool {
+        false
+    }
+
+    /// Prototype kinds the rule applies to
+    fn kind(&self) -> &PrototypeKind;
+
+    /// Options the rule reads, checked when the configuration is loaded
+    fn options(&self) -> &'static [RuleOption] {
+        &[]
+    }
+
+    /// Whether the rule looks at other prototypes, the index of every
+    /// prototype is only built if an enabled rule does.
+    fn uses_prototypes(&self) -> bool {
+        false
+    }
+
+    /// Whether the rule should be applied to the table.
+    fn check(&mut self, context: &RuleContext, table: &Table) -> bool;
+
+    fn apply(&mut self, context: &mut RuleContext, table: &mut Table) -> RuleResult;
+
+    /// A fresh instance that fixes a single file, with the same configuration
+    /// but none of the collected state.
+    fn fork(&self) -> Box<dyn Rule>;
+
+    /// Whether the rule collects state in [`Rule::merge`]. Files skipped by
+    /// the cache would be missing from it, so the cache is off while such a
+    /// rule is enabled.
+    fn is_stateful(&self) -> bool {
+        false
+    }
+
+    /// Adds what a fork collected from its file, forks are merged in path
+    /// order. The fork can be turned back into the rule's own type with
+    /// `(fork as Box<dyn Any>).downcast::<Self>()`.
+    fn merge(&mut self, _fork: Box<dyn Rule>) {}
+
+    /// Called once after every mod was visited.
+    fn finish(&mut self, _diagnostics: &mut Diagnostics) {}
+}
+
+/// A stateless rule made of plain functions.
+#[derive(Debug, Clone)]
 pub struct FixRule {
+    /// Stable identifier used to select the rule, groups are separated by dots
+    pub id: &'static str,
+    pub description: &'static str,
+    /// Line added to the changelog of mods the rule fixed something in
+    pub changelog: Option<&'static str>,
+    /// Whether the rule runs when no selection mentions it
     pub enabled: bool,
     pub kind: PrototypeKind,
-    pub filter: fn(&str, &Locales, &Table) -> bool,
-    pub action: fn(&str, &str, &Locales, &mut Table) -> Option<()>,
+    pub options: &'static [RuleOption],
+    pub filter: fn(&RuleContext, &Table) -> bool,
+    pub action: fn(&mut RuleContext, &mut Table) -> RuleResult,
+}
+
+impl Rule for FixRule {
+    fn id(&self) -> &'static str {
+        self.id
+    }
+
+    fn description(&self) -> &'static str {
+        self.description
+    }
+
+    fn changelog(&self) -> Option<&'static str> {
+        self.changelog
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn kind(&self) -> &PrototypeKind {
+        &self.kind
+    }
+
+    fn options(&self) -> &'static [RuleOption] {
+        self.options
+    }
+
+    fn check(&mut self, context: &RuleContext, table: &Table) -> bool {
+        (self.filter)(context, table)
+    }
+
+    fn apply(&mut self, context: &mut RuleContext, table: &mut Table) -> RuleResult {
+        (self.action)(context, table)
+    }
+
+    fn fork(&self) -> Box<dyn Rule> {
+        Box::new(self.clone())
+    }
+}
+
+/// An option a rule reads from `[rules.options."<id>"]`.
+#[derive(Debug, Clone, Copy)]
+pub struct RuleOption {
+    pub name: &'static str,
+    /// Returns why a configured value can't be used
+    pub check: fn(&toml::Value) -> Result<(), String>,
+}
+
+impl RuleOption {
+    /// Checks that a value deserializes as `T`, the type the rule reads it as.
+    pub fn typed<T: DeserializeOwned>(value: &toml::Value) -> Result<(), String> {
+        value
+            .clone()
+            .try_into::<T>()
+            .map(drop)
+            .map_err(|error| error.to_string().trim().to_string())
+    }
+
+    pub fn positive_number(value: &toml::Value) -> Result<(), String> {
+        let number = value
+            .clone()
+            .try_into::<f64>()
+            .map_err(|error| error.to_string().trim().to_string())?;
+
+        if number > 0.0 {
+            Ok(())
+        } else {
+            Err(format!("expected a number greater than 0, found {number}"))
+        }
+    }
+}
+
+/// Options of a single rule, configured under `[rules.options."<id>"]`.
+///
+/// Values are checked against the [`RuleOption`]s of the rule when the
+/// configuration is loaded, so reading them with the declared type succeeds.
+#[derive(Debug, Default, Clone)]
+pub struct RuleOptions(toml::Table);
+
+impl RuleOptions {
+    pub const fn new(table: toml::Table) -> Self {
+        Self(table)
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, key: impl AsRef<str>) -> Option<T> {
+        self.0
+            .get(key.as_ref())
+            .and_then(|value| value.clone().try_into().ok())
+    }
+
+    pub fn get_or<T: DeserializeOwned>(&self, key: impl AsRef<str>, default: T) -> T {
+        self.get(key).unwrap_or(default)
+    }
+
+    /// Overwrites options with the ones from `table`.
+    pub fn merge(&mut self, table: &toml::Table) {
+        self.0.extend(
+            table
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone())),
+        );
+    }
+}
+
+/// Rules enabled for a mod together with their options.
+#[derive(Debug)]
+pub struct RuleSet {
+    /// Indexes of the enabled rules in [`all`], every rule set shares the same
+    /// instances
+    pub rules: Vec<usize>,
+    pub info: Vec<InfoRule>,
+    /// Options of every enabled rule, keyed by rule id
+    pub options: HashMap<&'static str, RuleOptions>,
+}
+
+impl RuleSet {
+    pub fn new(selection: &RuleSelection, mut options: HashMap<String, RuleOptions>) -> Self {
+        let rules = all()
+            .into_iter()
+            .enumerate()
+            .filter(|(_, rule)| selection.is_enabled(rule.id(), rule.enabled()))
+            .collect::<Vec<_>>();
+        let info = info::all()
+            .into_iter()
+            .filter(|rule| selection.is_enabled(rule.id, rule.enabled))
+            .collect::<Vec<_>>();
+
+        Self {
+            options: rules
+                .iter()
+                .map(|(_, rule)| rule.id())
+                .chain(info.iter().map(|rule| rule.id))
+                .map(|id| (id, options.remove(id).unwrap_or_default()))
+                .collect(),
+            rules: rules.into_iter().map(|(index, _)| index).collect(),
+            info,
+        }
+    }
 }
 
+impl RuleSet {
+    /// Identifies the enabled rules and their options, for caching results.
+    pub fn fingerprint(&self) -> String {
+        let mut ids = self.options.keys().collect::<Vec<_>>();
+
+        ids.sort();
+
+        let mut fingerprint = String::new();
+
+        for id in ids {
+            writeln!(fingerprint, "{id} {}", self.options[id].0).unwrap();
+        }
+
+        fingerprint
+    }
+}
+
+/// Every known rule, in the order they are applied.
+pub fn all() -> Vec<Box<dyn Rule>> {
+    vec![
+        Box::new(FIX_RECIPE),
+        Box::new(FIX_BEAM_GRAPHICS),
+        Box::new(FIX_MACHINE_GRAPHICS),
+        Box::new(FIX_OFFSHORE_PUMP_GRAPHICS),
+        Box::new(FIX_TURRET_GRAPHICS),
+        Box::new(FIX_HIGH_RES_GRAPHICS),
+        Box::new(FIX_FLUID_BOXES),
+    ]
+}
+
+/// Options of the rule `id`, including the info.json ones.
+pub fn options(id: &str) -> Option<&'static [RuleOption]> {
+    all()
+        .iter()
+        .find(|rule| rule.id() == id)
+        .map(|rule| rule.options())
+        .or_else(|| {
+            info::all()
+                .iter()
+                .find(|rule| rule.id == id)
+                .map(|rule| rule.options)
+        })
+}
+
+/// Ids of every known rule, including the info.json ones.
+pub fn ids() -> Vec<&'static str> {
+    all()
+        .iter()
+        .map(|rule| rule.id())
+        .chain(info::all().iter().map(|rule| rule.id))
+        .collect()
+}
+
+/// Enables or disables rules by id at runtime.
+///
+/// Patterns are either an exact rule id, a group ending with `.*`
+/// (e.g. `graphics.*`) or `*` for every rule. A selection is made of layers,
+/// e.g. the configuration, a mod override and the command line. The last layer
+/// with a pattern matching a rule decides whether it runs, within a layer
+/// disabling wins over enabling.
+#[derive(Debug, Default, Clone)]
+pub struct RuleSelection {
+    /// Earliest layer first
+    layers: Vec<SelectionLayer>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct SelectionLayer {
+    enable: Vec<String>,
+    disable: Vec<String>,
+}
+
+impl SelectionLayer {
+    /// Whether the layer enables or disables the rule `id`, `None` if it
+    /// doesn't mention it.
+    fn decide(&self, id: &str) -> Option<bool> {
+        let matches = |patterns: &[String]| {
+            patterns
+                .iter()
+                .any(|pattern| RuleSelection::matches(pattern, id))
+        };
+
+        if matches(&self.disable) {
+            Some(false)
+        } else if matches(&self.enable) {
+            Some(true)
+        } else {
+            None
+        }
+    }
+}
+
+impl RuleSelection {
+    /// A selection with a single layer.
+    pub fn new(enable: Vec<String>, disable: Vec<String>) -> Self {
+        Self {
+            layers: vec![SelectionLayer { enable, disable }],
+        }
+    }
+
+    /// Adds the layers of `other` on top of ours, so its patterns override ours.
+    pub fn merge(&mut self, other: Self) {
+        self.layers.extend(other.layers);
+    }
+
+    pub fn matches(pattern: &str, id: &str) -> bool {
+        if pattern == "*" {
+            true
+        } else if let Some(group) = pattern.strip_suffix(".*") {
+            id.strip_prefix(group)
+                .is_some_and(|rest| rest.starts_with('.'))
+        } else {
+            pattern == id
+        }
+    }
+
+    /// Whether the rule `id` runs, `enabled` is its default.
+    pub fn is_enabled(&self, id: &str, enabled: bool) -> bool {
+        self.layers
+            .iter()
+            .rev()
+            .find_map(|layer| layer.decide(id))
+            .unwrap_or(enabled)
+    }
+
+    /// Returns patterns that don't match any known rule.
+    pub fn unknown_patterns(&self) -> Vec<&str> {
+        let ids = ids();
+
+        self.layers
+            .iter()
+            .flat_map(|layer| layer.enable.iter().chain(&layer.disable))
+            .filter(|pattern| !ids.iter().any(|id| Self::matches(pattern, id)))
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selection(enable: &[&str], disable: &[&str]) -> RuleSelection {
+        RuleSelection::new(
+            enable.iter().map(ToString::to_string).collect(),
+            disable.iter().map(ToString::to_string).collect(),
+        )
+    }
+
+    #[test]
+    fn matches_ids_groups_and_everything() {
+        assert!(RuleSelection::matches("graphics.beam", "graphics.beam"));
+        assert!(RuleSelection::matches("graphics.*", "graphics.beam"));
+        assert!(RuleSelection::matches("*", "fluid-boxes"));
+        assert!(!RuleSelection::matches("graphics.*", "graphics"));
+        assert!(!RuleSelection::matches("graphics.*", "graphicsx.beam"));
+        assert!(!RuleSelection::matches("graphics.beam", "graphics.turret"));
+    }
+
+    #[test]
+    fn disabling_wins_within_a_layer() {
+        let selection = selection(&["graphics.beam"], &["graphics.*"]);
+
+        assert!(!selection.is_enabled("graphics.beam", false));
+    }
+
+    #[test]
+    fn unmentioned_rules_keep_their_default() {
+        let selection = selection(&["graphics.*"], &[]);
+
+        assert!(selection.is_enabled("fluid-boxes", true));
+        assert!(!selection.is_enabled("recipe.localised-name", false));
+    }
+
+    #[test]
+    fn exact_id_in_a_later_layer_overrides_a_group() {
+        let mut layered = selection(&[], &["graphics.*"]);
+
+        layered.merge(selection(&["graphics.hr-version"], &[]));
+
+        assert!(layered.is_enabled("graphics.hr-version", false));
+        assert!(!layered.is_enabled("graphics.beam", true));
+    }
+
+    #[test]
+    fn group_in_a_later_layer_overrides_an_exact_id() {
+        let mut layered = selection(&["graphics.beam"], &[]);
+
+        layered.merge(selection(&[], &["graphics.*"]));
+
+        assert!(!layered.is_enabled("graphics.beam", false));
+
+        let mut layered = selection(&[], &["graphics.beam"]);
+
+        layered.merge(selection(&["*"], &[]));
+
+        assert!(layered.is_enabled("graphics.beam", false));
+    }
+
+    #[test]
+    fn layers_without_a_match_fall_through() {
+        let mut layered = selection(&["graphics.beam"], &[]);
+
+        layered.merge(selection(&[], &["recipe.*"]));
+
+        assert!(layered.is_enabled("graphics.beam", false));
+    }
+
+    /// Counts the tables it was applied to over the whole run.
+    #[derive(Debug, Default)]
+    struct CountTables {
+        count: usize,
+    }
+
+    impl Rule for CountTables {
+        fn id(&self) -> &'static str {
+            "count-tables"
+        }
+
+        fn description(&self) -> &'static str {
+            "Counts tables"
+        }
+
+        fn kind(&self) -> &PrototypeKind {
+            &PrototypeKind::None
+        }
+
+        fn check(&mut self, _context: &RuleContext, _table: &Table) -> bool {
+            true
+        }
+
+        fn apply(&mut self, _context: &mut RuleContext, _table: &mut Table) -> RuleResult {
+            self.count += 1;
+
+            Ok(())
+        }
+
+        fn fork(&self) -> Box<dyn Rule> {
+            Box::new(Self::default())
+        }
+
+        fn is_stateful(&self) -> bool {
+            true
+        }
+
+        fn merge(&mut self, fork: Box<dyn Rule>) {
+            if let Ok(fork) = (fork as Box<dyn Any>).downcast::<Self>() {
+                self.count += fork.count;
+            }
+        }
+    }
+
+    #[test]
+    fn forks_merge_back_into_the_rule() {
+        let mut rule = CountTables { count: 1 };
+        let mut fork = CountTables { count: 2 };
+
+        assert_eq!(rule.fork().as_ref().id(), "count-tables");
+
+        fork.count += 1;
+        rule.merge(Box::new(fork));
+        rule.merge(Box::new(FIX_RECIPE));
+
+        assert_eq!(rule.count, 4);
+    }
+
+    #[test]
+    fn rule_sets_refer_to_the_shared_rules() {
+        let rules = all();
+        let mut only_graphics = selection(&[], &["*"]);
+
+        only_graphics.merge(selection(&["graphics.*"], &[]));
+
+        let rule_set = RuleSet::new(&only_graphics, HashMap::new());
+
+        assert!(!rule_set.rules.is_empty());
+        assert!(rule_set
+            .rules
+            .iter()
+            .all(|index| rules[*index].id().starts_with("graphics.")));
+        assert!(rule_set
+            .rules
+            .iter()
+            .all(|index| rule_set.options.contains_key(rules[*index].id())));
+    }
+}
@@ -0,0 +1,209 @@
+use crate::Value;
+use full_moon::{ast, tokenizer};
+use std::fmt;
+
+/// How a table is reached from its parent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    /// `key = value` or `["key"] = value`
+    Key(String),
+    /// Positional element or `[n] = value`, 1-based like in Lua
+    Index(usize),
+    /// `[expression] = value` with a key that isn't a literal
+    Expression(String),
+}
+
+impl PathSegment {
+    /// Segment of a field, `position` is the 1-based index among the
+    /// positional fields of its table.
+    pub fn of_field(field: &ast::Field, position: usize) -> Self {
+        match field {
+            ast::Field::NameKey { key, .. } => match key.token_type() {
+                tokenizer::TokenType::Identifier { identifier } => {
+                    Self::Key(identifier.to_string())
+                }
+                _ => Self::Expression(key.to_string()),
+            },
+            ast::Field::ExpressionKey { key, .. } => match Value::from_raw(key.clone()) {
+                Some(Value::String(key)) => Self::Key(key),
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                Some(Value::Number(index)) if index >= 1.0 && index.fract() == 0.0 => {
+                    Self::Index(index as usize)
+                }
+                _ => Self::Expression(key.to_string().trim().to_string()),
+            },
+            _ => Self::Index(position),
+        }
+    }
+}
+
+/// Where a table sits, as the keys leading to it from the enclosing prototype,
+/// e.g. `assembling-machine/foo > graphics_set > animation > layers[2]`.
+#[derive(Debug, Default, Clone)]
+pub struct TablePath {
+    /// `type` and `name` of the enclosing prototype, `None` outside of prototypes
+    pub prototype: Option<(String, String)>,
+    /// Keys from the prototype, or from the outermost table outside of
+    /// prototypes, empty for the prototype itself
+    pub segments: Vec<PathSegment>,
+}
+
+impl TablePath {
+    /// Whether the table is a prototype rather than a table nested in one.
+    pub const fn is_prototype(&self) -> bool {
+        self.prototype.is_some() && self.segments.is_empty()
+    }
+
+    /// Type of the enclosing prototype.
+    pub fn prototype_kind(&self) -> Option<&str> {
+        self.prototype.as_ref().map(|(kind, _)| kind.as_str())
+    }
+
+    /// The key the table is stored under, skipping indexes, so every layer of
+    /// `animation = {layers = {...}}` has the parent key `layers`.
+    pub fn parent_key(&self) -> Option<&str> {
+        self.keys().next_back()
+    }
+
+    /// Whether any enclosing table is stored under `key`.
+    pub fn is_under(&self, key: &str) -> bool {
+        self.keys().any(|parent| parent == key)
+    }
+
+    fn keys(&self) -> impl DoubleEndedIterator<Item = &str> {
+        self.segments.iter().filter_map(|segment| match segment {
+            PathSegment::Key(key) => Some(key.as_str()),
+            _ => None,
+        })
+    }
+}
+
+impl fmt::Display for TablePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = if let Some((kind, name)) = &self.prototype {
+            write!(f, "{kind}/{name}")?;
+
+            false
+        } else {
+            true
+        };
+
+        for segment in &self.segments {
+            match segment {
+                PathSegment::Key(key) => {
+                    if !first {
+                        f.write_str(" > ")?;
+                    }
+
+                    f.write_str(key)?;
+                }
+                PathSegment::Index(index) => write!(f, "[{index}]")?,
+                PathSegment::Expression(key) => write!(f, "[{key}]")?,
+            }
+
+            first = false;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(prototype: Option<(&str, &str)>, segments: Vec<PathSegment>) -> TablePath {
+        TablePath {
+            prototype: prototype.map(|(kind, name)| (kind.to_string(), name.to_string())),
+            segments,
+        }
+    }
+
+    fn key(key: &str) -> PathSegment {
+        PathSegment::Key(key.to_string())
+    }
+
+    #[test]
+    fn displays_paths() {
+        let layer = path(
+            Some(("assembling-machine", "foo")),
+            vec![
+                key("graphics_set"),
+                key("animation"),
+                key("layers"),
+                PathSegment::Index(2),
+            ],
+        );
+
+        assert_eq!(
+            layer.to_string(),
+            "assembling-machine/foo > graphics_set > animation > layers[2]"
+        );
+        assert_eq!(
+            path(
+                None,
+                vec![key("sprite"), PathSegment::Expression("i + 1".to_string())]
+            )
+            .to_string(),
+            "sprite[i + 1]"
+        );
+        assert_eq!(
+            path(Some(("item", "bar")), Vec::new()).to_string(),
+            "item/bar"
+        );
+    }
+
+    #[test]
+    fn parent_keys_skip_indexes() {
+        let layer = path(
+            Some(("furnace", "foo")),
+            vec![key("animation"), key("layers"), PathSegment::Index(1)],
+        );
+
+        assert!(!layer.is_prototype());
+        assert_eq!(layer.prototype_kind(), Some("furnace"));
+        assert_eq!(layer.parent_key(), Some("layers"));
+        assert!(layer.is_under("animation"));
+        assert!(!layer.is_under("graphics_set"));
+        assert!(path(Some(("furnace", "foo")), Vec::new()).is_prototype());
+        assert!(!path(None, Vec::new()).is_prototype());
+    }
+
+    #[test]
+    fn segments_of_fields() {
+        let ast =
+            full_moon::parse(r#"local _ = { a = 1, 2, ["b"] = 3, [4] = 5, [x] = 6, 7 }"#).unwrap();
+        let Some(ast::Stmt::LocalAssignment(assignment)) = ast.nodes().stmts().next() else {
+            panic!("expected a local assignment");
+        };
+        let Some(ast::Expression::TableConstructor(table)) = assignment.expressions().iter().next()
+        else {
+            panic!("expected a table");
+        };
+
+        let mut position = 0;
+        let segments = table
+            .fields()
+            .iter()
+            .map(|field| {
+                if matches!(field, ast::Field::NoKey(_)) {
+                    position += 1;
+                }
+
+                PathSegment::of_field(field, position)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            segments,
+            [
+                key("a"),
+                PathSegment::Index(1),
+                key("b"),
+                PathSegment::Index(4),
+                PathSegment::Expression("x".to_string()),
+                PathSegment::Index(2),
+            ]
+        );
+    }
+}
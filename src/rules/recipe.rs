@@ -1,89 +1,90 @@
-use super::{FixRule, PrototypeKind};
+use super::{FixRule, OrFail, PrototypeKind, RuleError, RuleOption};
 use crate::{string_expr, Table};
-use owo_colors::OwoColorize;
 
 pub const FIX_RECIPE: FixRule = FixRule {
+    id: "recipe.localised-name",
+    description: "Adds `localised_name` to recipes named after a product that has its own locale",
+    changelog: Some(
+        "Added `localised_name` to recipes named after a product that has its own locale",
+    ),
     enabled: false,
     kind: PrototypeKind::Single("recipe"),
-    filter: |prototype_name, locales, table| {
-        locales
-            .find_category_by_key(prototype_name)
-            .is_none_or(|category| category != "recipe-name")
-            && (table.contains_key("main_product") || table.contains_key("results"))
+    options: &[RuleOption {
+        name: "skip",
+        check: RuleOption::typed::<Vec<String>>,
+    }],
+    filter: |context, table| {
+        context.name.is_some_and(|name| {
+            context
+                .locales
+                .find_category_by_key(name)
+                .is_none_or(|category| category != "recipe-name")
+        }) && (table.contains_key("main_product") || table.contains_key("results"))
             && !table.contains_key("localised_name")
     },
-    action: |mod_name, prototype_name, locales, table| {
+    action: |context, table| {
+        let prototype_name = context.name.or_skip("the recipe has no name")?;
         let name: String = if let Some(product) = table.get_value("main_product") {
             product
         } else {
-            let results: Table = table.get_value("results")?;
+            let results: Table = table
+                .get_value("results")
+                .or_fail("results isn't a table literal")?;
 
             if results.len() != 1 {
-                println!(
-                    "[{}] Failure at fixing recipe called {}: results contain more than 1 element, can't choose.",
-                    mod_name.bright_red(),
-                    prototype_name.bright_blue(),
+                return Err(
+                    RuleError::failed("results contain more than 1 element, can't choose")
+                        .at(table.position_of("results")),
                 );
-
-                return None;
             }
 
-            let result: Table = results.get_value_at(0)?;
+            let result: Table = results
+                .get_value_at(0)
+                .or_fail("the result isn't a table literal")?;
 
-            result.get_value("name")?
+            result
+                .get_value("name")
+                .or_fail("the result has no literal name")?
         };
 
         if name == prototype_name
-            && locales
+            && context
+                .locales
                 .find_category_by_key(prototype_name)
                 .is_some_and(|category_name| category_name == "recipe-name")
         {
-            return None;
+            return Err(RuleError::skipped(
+                "the recipe is named after its product and has a recipe locale",
+            ));
         }
 
-        if name.contains("void") {
-            println!(
-                "[{}] Failure at fixing recipe called {}: it's a void!",
-                mod_name.bright_red(),
-                prototype_name.bright_blue(),
-            );
-
-            return None;
-        } else if name.contains("slag") {
-            println!(
-                "[{}] Failure at fixing recipe called {} (it's a slag!)",
-                mod_name.bright_red(),
-                prototype_name.bright_blue(),
-            );
+        let skip = context
+            .options
+            .get_or("skip", vec!["void".to_string(), "slag".to_string()]);
 
-            return None;
+        if let Some(pattern) = skip.iter().find(|pattern| name.contains(pattern.as_str())) {
+            return Err(RuleError::skipped(format!(
+                "product {name} matches skipped pattern `{pattern}`"
+            )));
         }
 
-        if let Some(category) =
-            locales.find_in_categories_by_key(&["item-name", "fluid-name", "entity-name"], &name)
-        {
-            let mut localised = Table::default();
+        let category = context
+            .locales
+            .find_in_categories_by_key(&["item-name", "fluid-name", "entity-name"], &name)
+            .or_fail(format!("there is no locale category for {name}"))?;
 
-            localised.push(string_expr(format!("{category}.{name}")));
+        let mut localised = Table::default();
 
-            let name_pos = table.index_of("name")?;
+        localised.push(string_expr(format!("{category}.{name}")));
 
-            table.insert_after(name_pos, "localised_name", localised);
+        let name_pos = table.index_of("name").or_skip("the recipe has no name")?;
 
-            println!(
-                "[{}] Fixed recipe called {}",
-                mod_name.bright_green(),
-                prototype_name.bright_blue()
-            );
-        } else {
-            println!(
-                "[{}] Failure at fixing recipe called {} (there is no category for {})",
-                mod_name.bright_red(),
-                prototype_name.bright_blue(),
-                name.bright_yellow()
-            );
-        }
+        table.insert_after(name_pos, "localised_name", localised);
+
+        context
+            .diagnostics
+            .fixed(format!("added localised_name {category}.{name}"));
 
-        Some(())
+        Ok(())
     },
 };
@@ -0,0 +1,327 @@
+use crate::prototypes::brace_offset;
+use full_moon::{
+    ast::{span::ContainedSpan, Ast, Expression, Field, LuaVersion, TableConstructor},
+    node::Node,
+    tokenizer::{Lexer, LexerResult, TokenReference, TokenType},
+    visitors::Visitor,
+};
+use std::{collections::HashSet, error::Error};
+
+/// Prefix the table is formatted behind, stylua only formats whole statements.
+const PREFIX: &str = "local _ = ";
+
+/// Splices the changed tables of `ast` back into `source`, leaving every other
+/// byte of the file as it was.
+///
+/// `changed` holds the byte offsets of the opening braces of tables that rules
+/// rewrote. Only the outermost of them is formatted, indented like the line it
+/// starts on.
+pub fn splice(
+    source: &str,
+    ast: &Ast,
+    changed: &HashSet<usize>,
+    config: stylua_lib::Config,
+) -> Result<String, Box<dyn Error>> {
+    let mut collector = ChangedTables {
+        changed,
+        tables: Vec::new(),
+    };
+
+    collector.visit_ast(ast);
+
+    let mut output = source.to_string();
+
+    // Back to front, so earlier offsets stay valid
+    for (start, end, table) in collector.tables.into_iter().rev() {
+        let formatted = format_table(&table.to_string(), config)?;
+
+        let line_start = source[..start].rfind('\n').map_or(0, |index| index + 1);
+        let line = &source[line_start..start];
+        let indent = &line[..line.len() - line.trim_start().len()];
+
+        let formatted = indent_lines(&formatted, indent);
+
+        output.replace_range(start..end, &formatted);
+    }
+
+    Ok(output)
+}
+
+/// Records in `changed` the tables of `new` that differ from `old`, the table
+/// as rules left it.
+///
+/// Tables with the same keys are compared field by field, so a field holding
+/// a table is followed into instead of marking every table around it. A
+/// table is marked as soon as one of its fields was added, removed or
+/// replaced with something else. Tables built by rules aren't in the source,
+/// their changes mark the closest table around them that is.
+pub fn mark_changed(old: &TableConstructor, new: &TableConstructor, changed: &mut HashSet<usize>) {
+    mark_fields(old, new, brace_offset(new), changed);
+}
+
+fn mark_fields(
+    old: &TableConstructor,
+    new: &TableConstructor,
+    owner: usize,
+    changed: &mut HashSet<usize>,
+) {
+    if old.similar(new) {
+        return;
+    }
+
+    let owner = if is_from_source(new) {
+        brace_offset(new)
+    } else {
+        owner
+    };
+    let mut nested = Vec::new();
+
+    if old.fields().len() != new.fields().len() {
+        changed.insert(owner);
+
+        return;
+    }
+
+    for (old_field, new_field) in old.fields().iter().zip(new.fields()) {
+        if old_field.similar(new_field) {
+            continue;
+        }
+
+        match (key_value(old_field), key_value(new_field)) {
+            (
+                Some((old_key, Expression::TableConstructor(old_value))),
+                Some((new_key, Expression::TableConstructor(new_value))),
+            ) if old_key == new_key
+                && (!is_from_source(new_value)
+                    || brace_offset(old_value) == brace_offset(new_value)) =>
+            {
+                nested.push((old_value, new_value));
+            }
+            _ => {
+                changed.insert(owner);
+
+                return;
+            }
+        }
+    }
+
+    for (old, new) in nested {
+        mark_fields(old, new, owner, changed);
+    }
+}
+
+/// Key and value of a field, positional fields have no key.
+fn key_value(field: &Field) -> Option<(Option<String>, &Expression)> {
+    match field {
+        Field::ExpressionKey { key, value, .. } => {
+            Some((Some(format!("[{}]", key.to_string().trim())), value))
+        }
+        Field::NameKey { key, value, .. } => {
+            Some((Some(key.to_string().trim().to_string()), value))
+        }
+        Field::NoKey(value) => Some((None, value)),
+        _ => None,
+    }
+}
+
+/// Whether the braces of `table` are in the source, braces built by rules
+/// start at the first byte, where no table of a Lua file can.
+fn is_from_source(table: &TableConstructor) -> bool {
+    brace_offset(table) > 0
+}
+
+/// Formats the text of a single table as if it started a line.
+pub fn format_table(table: &str, config: stylua_lib::Config) -> Result<String, Box<dyn Error>> {
+    let formatted = stylua_lib::format_code(
+        &format!("{PREFIX}{table}\n"),
+        config,
+        None,
+        stylua_lib::OutputVerification::Full,
+    )?;
+
+    Ok(formatted
+        .trim_end()
+        .strip_prefix(PREFIX)
+        .ok_or("formatted table doesn't start where expected")?
+        .to_string())
+}
+
+/// Indents every line of `code` after the first, except for lines continuing
+/// a multi-line string or comment, their content must stay as it is.
+fn indent_lines(code: &str, indent: &str) -> String {
+    let verbatim = match Lexer::new(code, LuaVersion::new()).collect() {
+        LexerResult::Ok(tokens) | LexerResult::Recovered(tokens, _) => tokens
+            .into_iter()
+            .filter(|token| {
+                matches!(
+                    token.token_type(),
+                    TokenType::StringLiteral { .. } | TokenType::MultiLineComment { .. }
+                )
+            })
+            .map(|token| token.start_position().bytes()..token.end_position().bytes())
+            .collect(),
+        LexerResult::Fatal(_) => Vec::new(),
+    };
+
+    let mut output = String::with_capacity(code.len());
+
+    for (index, character) in code.char_indices() {
+        output.push(character);
+
+        if character == '\n' && !verbatim.iter().any(|range| range.contains(&index)) {
+            output.push_str(indent);
+        }
+    }
+
+    output
+}
+
+/// Collects the outermost changed tables with the byte range they had in the source.
+struct ChangedTables<'a> {
+    changed: &'a HashSet<usize>,
+    tables: Vec<(usize, usize, TableConstructor)>,
+}
+
+impl Visitor for ChangedTables<'_> {
+    fn visit_table_constructor(&mut self, node: &TableConstructor) {
+        let (open, close) = node.braces().tokens();
+        let start = open.token().start_position().bytes();
+
+        if !self.changed.contains(&start)
+            || self.tables.last().is_some_and(|(_, end, _)| start < *end)
+        {
+            return;
+        }
+
+        // The braces keep their original positions, even if the fields changed
+        let end = close.token().end_position().bytes();
+
+        // Trivia around the braces stays in the source, only the table itself is replaced
+        let braces = ContainedSpan::new(
+            TokenReference::new(
+                Vec::new(),
+                open.token().clone(),
+                open.trailing_trivia().cloned().collect(),
+            ),
+            TokenReference::new(
+                close.leading_trivia().cloned().collect(),
+                close.token().clone(),
+                Vec::new(),
+            ),
+        );
+
+        self.tables
+            .push((start, end, node.clone().with_braces(braces)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn splice_table(source: &str, table: &str) -> String {
+        let ast = full_moon::parse(source).unwrap();
+        let changed = HashSet::from([source.find(table).unwrap()]);
+
+        splice(source, &ast, &changed, stylua_lib::Config::default()).unwrap()
+    }
+
+    #[test]
+    fn indents_the_table_like_its_line() {
+        let source = "if mods then\n\tdata:extend({ { type = \"item\", name = \"foo\", icon = \"__mod__/graphics/icons/foo.png\", stack_size = 50, subgroup = \"intermediate-product\" } })\nend\n";
+        let output = splice_table(source, "{ {");
+
+        assert_eq!(
+            output,
+            "if mods then\n\tdata:extend({\n\t\t{\n\t\t\ttype = \"item\",\n\t\t\tname = \"foo\",\n\t\t\ticon = \"__mod__/graphics/icons/foo.png\",\n\t\t\tstack_size = 50,\n\t\t\tsubgroup = \"intermediate-product\",\n\t\t},\n\t})\nend\n"
+        );
+    }
+
+    #[test]
+    fn keeps_long_strings_as_they_are() {
+        let source = "if mods then\n\tdata:extend({ { type = \"item\", name = \"foo\", icon = \"__mod__/graphics/icons/foo.png\", stack_size = 50, description = [[first\nsecond\n  third]] } })\nend\n";
+        let output = splice_table(source, "{ {");
+
+        assert!(
+            output.contains("\t\t\tdescription = [[first\nsecond\n  third]],\n"),
+            "{output}"
+        );
+        assert!(output.ends_with("\n\t\t},\n\t})\nend\n"), "{output}");
+    }
+
+    #[test]
+    fn formats_a_table_on_its_own() {
+        let config = stylua_lib::Config {
+            indent_type: stylua_lib::IndentType::Spaces,
+            indent_width: 2,
+            column_width: 30,
+            ..stylua_lib::Config::default()
+        };
+
+        assert_eq!(
+            format_table("{type='item',name='foo',stack_size=50}", config).unwrap(),
+            "{\n  type = \"item\",\n  name = \"foo\",\n  stack_size = 50,\n}"
+        );
+    }
+
+    #[test]
+    fn marks_the_innermost_changed_table() {
+        let source = "local _ = { type = \"pump\", collision_box = {{-0.4,-0.9},{0.4,0.9}}, fluid_box = { volume=100, pipe_connections = {} } }\n";
+        let ast = full_moon::parse(source).unwrap();
+        let Some(full_moon::ast::Stmt::LocalAssignment(assignment)) = ast.nodes().stmts().next()
+        else {
+            panic!("no local assignment");
+        };
+        let Some(Expression::TableConstructor(old)) = assignment.expressions().iter().next() else {
+            panic!("no table");
+        };
+
+        let mut new = crate::Table::new(old);
+        let (mut fluid_box, pos) = new.remove_value_pos::<crate::Table>("fluid_box").unwrap();
+        let (_, volume) = fluid_box.remove_value_pos::<f32>("volume").unwrap();
+
+        fluid_box.insert_at(volume, "volume", 200.0);
+        new.insert_at(pos, "fluid_box", fluid_box);
+
+        let mut changed = HashSet::new();
+
+        mark_changed(old, &new.into_constructor(), &mut changed);
+
+        assert_eq!(changed, HashSet::from([source.find("{ volume").unwrap()]));
+    }
+
+    #[test]
+    fn follows_tables_rebuilt_by_rules() {
+        let source = "local _ = { type = \"pump\", collision_box = {{-0.4,-0.9},{0.4,0.9}}, fluid_boxes = { { pipe_connections = {{ position = {0.3, -1.7} }} } } }\n";
+        let ast = full_moon::parse(source).unwrap();
+        let Some(full_moon::ast::Stmt::LocalAssignment(assignment)) = ast.nodes().stmts().next()
+        else {
+            panic!("no local assignment");
+        };
+        let Some(Expression::TableConstructor(old)) = assignment.expressions().iter().next() else {
+            panic!("no table");
+        };
+
+        // Like the fluid-boxes rule, every list is rebuilt around the tables it holds
+        let mut new = crate::Table::new(old);
+        let (mut fluid_boxes, pos) = new
+            .remove_value_pos::<Vec<crate::Table>>("fluid_boxes")
+            .unwrap();
+        let (mut connections, connections_pos) = fluid_boxes[0]
+            .remove_value_pos::<Vec<crate::Table>>("pipe_connections")
+            .unwrap();
+        let (_, position_pos) = connections[0]
+            .remove_value_pos::<[f32; 2]>("position")
+            .unwrap();
+
+        connections[0].insert_at(position_pos, "position", [0.0, -1.5]);
+        fluid_boxes[0].insert_at(connections_pos, "pipe_connections", connections);
+        new.insert_at(pos, "fluid_boxes", fluid_boxes);
+
+        let mut changed = HashSet::new();
+
+        mark_changed(old, &new.into_constructor(), &mut changed);
+
+        assert_eq!(changed, HashSet::from([source.find("{ position").unwrap()]));
+    }
+}
@@ -0,0 +1,139 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Names stylua looks for, in order of preference.
+const FILE_NAMES: [&str; 2] = ["stylua.toml", ".stylua.toml"];
+
+/// Settings this version of stylua understands, newer ones are ignored so a
+/// mod formatted with a newer stylua still gets its indentation respected.
+const KNOWN_KEYS: [&str; 9] = [
+    "column_width",
+    "line_endings",
+    "indent_type",
+    "indent_width",
+    "quote_style",
+    "no_call_parentheses",
+    "call_parentheses",
+    "collapse_simple_statement",
+    "sort_requires",
+];
+
+/// The closest stylua configuration in `dir` or any directory above it.
+pub fn find_config(dir: &Path) -> Option<PathBuf> {
+    let dir = dir.canonicalize().ok()?;
+
+    dir.ancestors()
+        .flat_map(|dir| FILE_NAMES.map(|name| dir.join(name)))
+        .find(|path| path.is_file())
+}
+
+/// Reads a stylua configuration, keeping only the settings we can pass on.
+pub fn load_config(path: &Path) -> Result<toml::Table, Box<dyn Error>> {
+    let mut table: toml::Table = toml::from_str(&fs::read_to_string(path)?)
+        .map_err(|error| format!("{}: {error}", path.display()))?;
+
+    table.retain(|key, _| KNOWN_KEYS.contains(&key));
+
+    Ok(table)
+}
+
+/// Indentation most lines of `source` use, as stylua `indent_type` and
+/// `indent_width`. Two spaces if nothing is indented.
+pub fn detect_indent(source: &str) -> (&'static str, usize) {
+    let mut tabs = 0;
+    let mut steps = HashMap::<usize, usize>::new();
+    let mut previous = 0;
+
+    for line in source.lines().filter(|line| !line.trim().is_empty()) {
+        let indent = &line[..line.len() - line.trim_start().len()];
+
+        if indent.starts_with('\t') {
+            tabs += 1;
+
+            continue;
+        }
+
+        // The step between nesting levels, not the absolute indentation
+        let width = indent.len();
+
+        if width > previous {
+            *steps.entry(width - previous).or_default() += 1;
+        }
+
+        previous = width;
+    }
+
+    let spaces = steps.values().sum::<usize>();
+
+    if tabs > spaces {
+        return ("Tabs", 4);
+    }
+
+    steps
+        .into_iter()
+        .max_by_key(|(width, count)| (*count, usize::MAX - width))
+        .map_or(("Spaces", 2), |(width, _)| ("Spaces", width))
+}
+
+/// Stylua settings for `source`, indentation that `table` leaves unset is
+/// detected from the source itself.
+pub fn settings(table: &toml::Table, source: &str) -> Result<stylua_lib::Config, toml::de::Error> {
+    let mut table = table.clone();
+    let (indent_type, indent_width) = detect_indent(source);
+
+    table
+        .entry("indent_type")
+        .or_insert_with(|| indent_type.into());
+    table
+        .entry("indent_width")
+        .or_insert_with(|| i64::try_from(indent_width).unwrap_or(2).into());
+
+    toml::Value::Table(table).try_into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_the_most_common_step() {
+        let source = "a = {\n    b = {\n        c = 1,\n    },\n    d = 2,\n}\n";
+
+        assert_eq!(detect_indent(source), ("Spaces", 4));
+    }
+
+    #[test]
+    fn ignores_continuation_lines_indented_further() {
+        let source =
+            "a = {\n  b = {\n    c = 1,\n          -- aligned\n  },\n  d = 2,\n  e = 3,\n}\n";
+
+        assert_eq!(detect_indent(source), ("Spaces", 2));
+    }
+
+    #[test]
+    fn detects_tabs() {
+        assert_eq!(detect_indent("a = {\n\tb = 1,\n\tc = 2,\n}\n"), ("Tabs", 4));
+    }
+
+    #[test]
+    fn defaults_to_two_spaces() {
+        assert_eq!(detect_indent("local a = 1\nlocal b = 2\n"), ("Spaces", 2));
+        assert_eq!(detect_indent(""), ("Spaces", 2));
+    }
+
+    #[test]
+    fn configured_indentation_wins() {
+        let mut table = toml::Table::new();
+
+        table.insert("indent_type".to_string(), "Spaces".into());
+
+        let config = settings(&table, "a = {\n\tb = 1,\n}\n").unwrap();
+
+        assert_eq!(config.indent_type, stylua_lib::IndentType::Spaces);
+        assert_eq!(config.indent_width, 4);
+    }
+}
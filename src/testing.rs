@@ -0,0 +1,14 @@
+use std::{fs, path::PathBuf};
+
+/// Empty directory for a single test, inside the system temp directory.
+///
+/// `name` has to be unique across the crate, tests run in parallel.
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("factorio-mods-fixer-{name}-{}", std::process::id()));
+
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    dir
+}
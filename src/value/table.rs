@@ -1,13 +1,14 @@
-use std::{collections::HashMap, vec};
+use std::{collections::HashMap, fmt, vec};
 
 use super::{IntoExpr, MaybeInto, Value};
 use full_moon::{
     ast::{self, punctuated, span, Expression},
     node::Node,
-    tokenizer, ShortString,
+    tokenizer::{self, Position},
+    ShortString,
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Field {
     at: usize,
     value: punctuated::Pair<ast::Field>,
@@ -62,6 +63,15 @@ impl Field {
         }
     }
 
+    /// Where the field starts in the source, `None` for fields created by rules.
+    #[must_use]
+    pub fn start_position(&self) -> Option<Position> {
+        self.value
+            .value()
+            .start_position()
+            .filter(|position| position.line() > 0)
+    }
+
     #[must_use]
     pub fn get_trailing_trivia(&self) -> Vec<&tokenizer::Token> {
         self.value.value().surrounding_trivia().0
@@ -91,7 +101,7 @@ impl Field {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Table {
     braces: span::ContainedSpan,
     fields: Vec<Field>,
@@ -112,13 +122,26 @@ impl Table {
         }
     }
 
+    /// Position of the opening brace, `None` for tables created by rules.
+    #[must_use]
+    pub fn start_position(&self) -> Option<Position> {
+        Some(self.braces.tokens().0.token().start_position()).filter(|position| position.line() > 0)
+    }
+
+    pub fn position_of(&self, name: impl AsRef<str>) -> Option<Position> {
+        self.fields
+            .iter()
+            .find(|field| field.get_key().is_some_and(|key| key == name.as_ref()))
+            .and_then(Field::start_position)
+    }
+
     #[must_use]
-    pub fn len(&self) -> usize {
+    pub const fn len(&self) -> usize {
         self.fields.len()
     }
 
     #[must_use]
-    pub fn is_empty(&self) -> bool {
+    pub const fn is_empty(&self) -> bool {
         self.fields.is_empty()
     }
 
@@ -340,6 +363,12 @@ impl Default for Table {
     }
 }
 
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.clone().into_constructor())
+    }
+}
+
 impl IntoIterator for Table {
     type Item = Field;
 